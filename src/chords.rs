@@ -0,0 +1,87 @@
+use crate::schema::{KeyAction, TimedEvent};
+use crate::state::key_label;
+use enigo::Key;
+use std::ops::Range;
+
+/// Keys pressed together, such as Ctrl+Shift+S. Purely a view over the timeline.
+#[derive(Clone, Debug)]
+pub struct Chord {
+  /// The run of consecutive Down events that make up the chord.
+  pub downs: Range<usize>,
+  /// The matching Up event for each key that has one.
+  pub ups: Vec<usize>,
+  /// Keys in press order.
+  pub keys: Vec<Key>,
+}
+
+impl Chord {
+  /// Modifiers first, then the other keys in press order, e.g. `Ctrl+Shift+s`.
+  pub fn label(&self) -> String {
+    let mut keys = self.keys.clone();
+    keys.sort_by_key(|k| modifier_rank(*k));
+    keys.iter().map(key_label).collect::<Vec<_>>().join("+")
+  }
+
+  /// Every event index belonging to the chord, ascending.
+  pub fn indices(&self) -> Vec<usize> {
+    let mut all: Vec<usize> = self.downs.clone().chain(self.ups.iter().copied()).collect();
+    all.sort_unstable();
+    all
+  }
+}
+
+fn modifier_rank(key: Key) -> u8 {
+  match key {
+    Key::Control => 0,
+    Key::Alt => 1,
+    Key::Shift => 2,
+    Key::Meta => 3,
+    _ => 4,
+  }
+}
+
+/// Group runs of two or more Down events that start within `window_ms` of the first
+/// one. Nothing is released in between, so the holds overlap by construction.
+pub fn detect_chords(events: &[TimedEvent], window_ms: u64) -> Vec<Chord> {
+  let window = std::time::Duration::from_millis(window_ms);
+  let mut chords = Vec::new();
+  let mut i = 0;
+  while i < events.len() {
+    let KeyAction::Down(_) = events[i].action else {
+      i += 1;
+      continue;
+    };
+    let mut end = i + 1;
+    while end < events.len()
+      && matches!(events[end].action, KeyAction::Down(_))
+      && events[end].at.saturating_sub(events[i].at) <= window
+    {
+      end += 1;
+    }
+    if end - i >= 2 {
+      let keys: Vec<Key> = events[i..end]
+        .iter()
+        .filter_map(|e| match e.action {
+          KeyAction::Down(k) => Some(k),
+          _ => None,
+        })
+        .collect();
+      let ups = keys
+        .iter()
+        .filter_map(|k| {
+          events[end..]
+            .iter()
+            .position(|e| matches!(e.action, KeyAction::Up(u) if u == *k))
+            .map(|offset| end + offset)
+        })
+        .collect();
+      chords.push(Chord {
+        downs: i..end,
+        ups,
+        keys,
+      });
+    }
+    i = end;
+  }
+  chords
+}
@@ -0,0 +1,93 @@
+//! Headless mode: `auto-note --play <file> [--sample N] [--offset MS] [--repeat N]`.
+//!
+//! Plays sample `N` (1-based, default 1) from a saved samples file and exits without
+//! opening a window. Exit codes: 0 on success, 1 if the sample doesn't exist, 2 for bad
+//! arguments or an unreadable file.
+
+use auto_note::macro_play;
+use auto_note::state::AppState;
+use clap::Parser;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_NO_SAMPLE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+/// Play a saved sample without opening a window.
+#[derive(Parser)]
+#[command(name = "auto-note")]
+struct CliArgs {
+  /// Samples file to load (`.json` or `.bin`).
+  #[arg(long, value_name = "FILE")]
+  play: PathBuf,
+  /// Sample to play, counting from 1.
+  #[arg(long, value_name = "N", default_value_t = 1)]
+  sample: usize,
+  /// Playback offset in ms; negative starts early.
+  #[arg(
+    long = "offset",
+    value_name = "MS",
+    default_value_t = 0,
+    allow_negative_numbers = true
+  )]
+  offset_ms: i64,
+  /// Number of passes.
+  #[arg(long, value_name = "N", default_value_t = 1)]
+  repeat: u32,
+}
+
+/// Run the headless player on `args` (without the program name) and return the
+/// process exit code.
+pub fn run(args: &[String]) -> i32 {
+  let argv = std::iter::once("auto-note").chain(args.iter().map(String::as_str));
+  let cli = match CliArgs::try_parse_from(argv) {
+    Ok(cli) => cli,
+    Err(e) => {
+      // `--help` and `--version` also end up here, printed to stdout.
+      let _ = e.print();
+      return if e.use_stderr() { EXIT_USAGE } else { EXIT_OK };
+    }
+  };
+  let state = AppState::new();
+  if let Err(e) = state.load_samples(&cli.play) {
+    eprintln!("failed to load {}: {}", cli.play.display(), e);
+    return EXIT_USAGE;
+  }
+  let sample = cli
+    .sample
+    .checked_sub(1)
+    .and_then(|idx| state.samples.lock().unwrap().get(idx).cloned());
+  let Some(sample) = sample else {
+    eprintln!("sample {} not found in {}", cli.sample, cli.play.display());
+    return EXIT_NO_SAMPLE;
+  };
+  *state.playback_offset_ms.lock().unwrap() = cli.offset_ms;
+  *state.playback_repeat.lock().unwrap() = cli.repeat;
+  let opts = state.playback_options(&sample);
+  println!(
+    "playing \"{}\" ({} events, offset {} ms)",
+    sample.name,
+    sample.events.len(),
+    opts.offset_ms
+  );
+  let stop = Arc::new(AtomicBool::new(false));
+  let paused = Arc::new(AtomicBool::new(false));
+  let handle = macro_play::play_timeline_async(
+    sample.events,
+    stop,
+    paused,
+    opts,
+    state.log_lines.clone(),
+    state.playback_report.clone(),
+  );
+  let _ = handle.join();
+  for line in state.log_lines.lock().unwrap().iter() {
+    println!("{}", line);
+  }
+  if let Some(report) = *state.playback_report.lock().unwrap() {
+    println!("{}", report);
+  }
+  EXIT_OK
+}
@@ -0,0 +1,120 @@
+//! Line-based command server for driving the recorder from other tools.
+//!
+//! Listens on localhost only. Each connection sends one command per line and gets
+//! `OK` or `ERR <reason>` back:
+//!
+//! - `PLAY N`: play sample `N` (1-based, as shown in the app)
+//! - `STOP`: stop playback
+//! - `PAUSE` / `RESUME`: pause or resume playback
+//! - `RECORD START` / `RECORD STOP`: start or stop recording
+
+use crate::state::AppState;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::thread;
+
+/// Environment variable holding the port to listen on; the app starts no server
+/// without it.
+pub const PORT_ENV: &str = "AUTO_NOTE_COMMAND_PORT";
+
+/// A parsed command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+  /// Play the sample at this 0-based index.
+  Play(usize),
+  Stop,
+  Pause,
+  Resume,
+  RecordStart,
+  RecordStop,
+}
+
+/// Parse one command line, case-insensitively.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+  let words: Vec<String> = line
+    .split_whitespace()
+    .map(str::to_ascii_uppercase)
+    .collect();
+  let words: Vec<&str> = words.iter().map(String::as_str).collect();
+  match words.as_slice() {
+    ["PLAY", n] => match n.parse::<usize>() {
+      Ok(n) if n >= 1 => Ok(Command::Play(n - 1)),
+      _ => Err(format!("bad sample number `{}`", n)),
+    },
+    ["STOP"] => Ok(Command::Stop),
+    ["PAUSE"] => Ok(Command::Pause),
+    ["RESUME"] => Ok(Command::Resume),
+    ["RECORD", "START"] => Ok(Command::RecordStart),
+    ["RECORD", "STOP"] => Ok(Command::RecordStop),
+    [] => Err("empty command".into()),
+    _ => Err(format!("unknown command `{}`", line.trim())),
+  }
+}
+
+/// Bind `127.0.0.1:port` and serve commands against `state` on a background thread.
+/// Returns the bound address; port 0 picks a free one.
+pub fn spawn(state: AppState, port: u16) -> io::Result<SocketAddr> {
+  let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+  let addr = listener.local_addr()?;
+  state.log(format!("Command server listening on {}", addr));
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      match stream {
+        Ok(stream) => {
+          let state = state.clone();
+          thread::spawn(move || {
+            if let Err(e) = serve(&state, stream) {
+              state.log(format!("Command connection closed: {}", e));
+            }
+          });
+        }
+        Err(e) => state.log(format!("Command server accept failed: {}", e)),
+      }
+    }
+  });
+  Ok(addr)
+}
+
+fn serve(state: &AppState, stream: TcpStream) -> io::Result<()> {
+  let mut out = stream.try_clone()?;
+  for line in BufReader::new(stream).lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let reply = match parse_command(&line).and_then(|cmd| run(state, cmd)) {
+      Ok(()) => "OK".to_string(),
+      Err(msg) => format!("ERR {}", msg),
+    };
+    writeln!(out, "{}", reply)?;
+  }
+  Ok(())
+}
+
+fn run(state: &AppState, cmd: Command) -> Result<(), String> {
+  state.log(format!("Command: {:?}", cmd));
+  match cmd {
+    Command::Play(idx) => {
+      let sample = state.samples.lock().unwrap().get(idx).cloned();
+      let sample = sample.ok_or_else(|| format!("no sample #{}", idx + 1))?;
+      state.playback_sample(&sample);
+    }
+    Command::Stop => state.stop_playback(),
+    Command::Pause => state.pause_playback(),
+    Command::Resume => state.resume_playback(),
+    Command::RecordStart => {
+      if state.recording.load(Ordering::SeqCst) {
+        return Err("already recording".into());
+      }
+      state.start_recording();
+    }
+    Command::RecordStop => {
+      if !state.recording.load(Ordering::SeqCst) {
+        return Err("not recording".into());
+      }
+      state.stop_recording();
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,171 @@
+use crate::persist::{
+  button_to_string, gamepad_button_to_string, key_to_string, string_to_button,
+  string_to_gamepad_button, string_to_key,
+};
+use crate::schema::{KeyAction, TimedEvent};
+use std::fmt;
+use std::time::Duration;
+
+const HEADER: &str = "at_ms,action,key";
+
+#[derive(Debug)]
+pub enum CsvError {
+  MissingHeader,
+  Line { line: usize, message: String },
+}
+
+impl fmt::Display for CsvError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CsvError::MissingHeader => write!(f, "missing `{}` header", HEADER),
+      CsvError::Line { line, message } => write!(f, "line {}: {}", line, message),
+    }
+  }
+}
+
+impl std::error::Error for CsvError {}
+
+/// One row per event: `at_ms,action,key`. `at_ms` keeps nanosecond precision as a
+/// decimal so a round trip is lossless.
+pub fn to_csv(events: &[TimedEvent]) -> String {
+  let mut out = String::from(HEADER);
+  out.push('\n');
+  for ev in events {
+    let (action, key) = match ev.action {
+      KeyAction::Down(k) => ("down", key_to_string(k)),
+      KeyAction::Up(k) => ("up", key_to_string(k)),
+      KeyAction::MouseMove { x, y } => ("move", Some(format!("{}:{}", x, y))),
+      KeyAction::ButtonDown(b) => ("button_down", button_to_string(b)),
+      KeyAction::ButtonUp(b) => ("button_up", button_to_string(b)),
+      KeyAction::Type(c) => ("type", Some(c.to_string())),
+      KeyAction::GamepadDown(b) => ("pad_down", Some(gamepad_button_to_string(b).to_string())),
+      KeyAction::GamepadUp(b) => ("pad_up", Some(gamepad_button_to_string(b).to_string())),
+    };
+    let Some(key) = key else {
+      continue;
+    };
+    out.push_str(&format!(
+      "{}.{:06},{},{}\n",
+      ev.at.as_millis(),
+      ev.at.subsec_nanos() % 1_000_000,
+      action,
+      quote(&key)
+    ));
+  }
+  out
+}
+
+pub fn from_csv(data: &str) -> Result<Vec<TimedEvent>, CsvError> {
+  let mut lines = data.lines().enumerate();
+  match lines.next() {
+    Some((_, header)) if header.trim() == HEADER => {}
+    _ => return Err(CsvError::MissingHeader),
+  }
+  let mut events = Vec::new();
+  for (i, raw) in lines {
+    let line = i + 1;
+    if raw.trim().is_empty() {
+      continue;
+    }
+    let err = |message: String| CsvError::Line { line, message };
+    let fields = split_fields(raw).map_err(err)?;
+    let [at, action, key] = fields.as_slice() else {
+      return Err(err(format!("expected 3 fields, found {}", fields.len())));
+    };
+    let at = parse_ms(at).ok_or_else(|| err(format!("bad at_ms `{}`", at)))?;
+    let unknown_key = || err(format!("unknown key `{}`", key));
+    let action = match action.as_str() {
+      "down" => KeyAction::Down(string_to_key(key).ok_or_else(unknown_key)?),
+      "up" => KeyAction::Up(string_to_key(key).ok_or_else(unknown_key)?),
+      "move" => {
+        let (x, y) = key
+          .split_once(':')
+          .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+          .ok_or_else(|| err(format!("bad position `{}`", key)))?;
+        KeyAction::MouseMove { x, y }
+      }
+      "button_down" => KeyAction::ButtonDown(string_to_button(key).ok_or_else(unknown_key)?),
+      "button_up" => KeyAction::ButtonUp(string_to_button(key).ok_or_else(unknown_key)?),
+      "type" => {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+          (Some(c), None) => KeyAction::Type(c),
+          _ => return Err(err(format!("expected one character, found `{}`", key))),
+        }
+      }
+      "pad_down" => KeyAction::GamepadDown(string_to_gamepad_button(key).ok_or_else(unknown_key)?),
+      "pad_up" => KeyAction::GamepadUp(string_to_gamepad_button(key).ok_or_else(unknown_key)?),
+      other => return Err(err(format!("unknown action `{}`", other))),
+    };
+    events.push(TimedEvent { at, action });
+  }
+  Ok(events)
+}
+
+fn parse_ms(s: &str) -> Option<Duration> {
+  let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+  if frac.len() > 6 || !frac.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  let ms: u64 = whole.parse().ok()?;
+  let nanos: u64 = if frac.is_empty() {
+    0
+  } else {
+    format!("{:0<6}", frac).parse().ok()?
+  };
+  Some(Duration::from_millis(ms) + Duration::from_nanos(nanos))
+}
+
+fn quote(field: &str) -> String {
+  if field.contains([',', '"']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+fn split_fields(line: &str) -> Result<Vec<String>, String> {
+  let mut fields = Vec::new();
+  let mut current = String::new();
+  let mut chars = line.chars().peekable();
+  let mut in_quotes = false;
+  while let Some(c) = chars.next() {
+    match (c, in_quotes) {
+      ('"', true) if chars.peek() == Some(&'"') => {
+        chars.next();
+        current.push('"');
+      }
+      ('"', true) => in_quotes = false,
+      ('"', false) if current.is_empty() => in_quotes = true,
+      (',', false) => fields.push(std::mem::take(&mut current)),
+      _ => current.push(c),
+    }
+  }
+  if in_quotes {
+    return Err("unterminated quote".into());
+  }
+  fields.push(current);
+  Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use enigo::Key;
+
+  #[test]
+  fn round_trip_keeps_events() {
+    let ev = |nanos: u64, action| TimedEvent { at: Duration::from_nanos(nanos), action };
+    let events = vec![
+      ev(0, KeyAction::Down(Key::Shift)),
+      ev(12_500_000, KeyAction::Down(Key::Layout('a'))),
+      ev(40_000_123, KeyAction::Up(Key::Layout('a'))),
+      ev(41_000_000, KeyAction::Down(Key::Layout(','))),
+      ev(45_000_000, KeyAction::Up(Key::Layout(','))),
+      ev(60_000_000, KeyAction::Up(Key::Shift)),
+      ev(75_000_000, KeyAction::Down(Key::Space)),
+      ev(75_000_000, KeyAction::Up(Key::Space)),
+    ];
+    assert_eq!(from_csv(&to_csv(&events)).unwrap(), events);
+  }
+}
@@ -0,0 +1,333 @@
+use crate::schema::{KeyAction, TimedEvent};
+use enigo::{Key, MouseButton};
+use image::{ImageFormat, Rgb, RgbImage};
+use midly::num::{u4, u7, u15, u24, u28};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Cursor;
+use std::time::Duration;
+
+/// MIDI resolution used by `export_midi`, in ticks per quarter note.
+pub const MIDI_TICKS_PER_BEAT: u16 = 480;
+const MIDI_VELOCITY: u8 = 100;
+
+/// Height of the time axis strip under the lanes in `render_timeline_png`, in pixels.
+const PNG_AXIS_HEIGHT: u32 = 24;
+/// Margin left and right of the plotted time range, in pixels.
+const PNG_MARGIN: u32 = 12;
+/// Minimum distance between labelled axis ticks, in pixels.
+const PNG_TICK_SPACING: f64 = 70.0;
+/// Axis label glyphs are drawn at this many pixels per font pixel.
+const PNG_FONT_SCALE: u32 = 2;
+const PNG_BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+const PNG_LANE: Rgb<u8> = Rgb([36, 36, 36]);
+const PNG_DOWN: Rgb<u8> = Rgb([120, 220, 120]);
+const PNG_UP: Rgb<u8> = Rgb([220, 120, 120]);
+const PNG_HOLD: Rgb<u8> = Rgb([70, 110, 70]);
+const PNG_AXIS: Rgb<u8> = Rgb([170, 170, 170]);
+
+/// Render a timeline as an AutoHotkey (v1) script that replays it with Send/Sleep.
+pub fn export_ahk(sample: &[TimedEvent]) -> String {
+  let mut out = String::new();
+  out.push_str("#NoEnv\nSendMode Input\nCoordMode, Mouse, Screen\n\n");
+  let mut last_ms: u128 = 0;
+  for ev in sample {
+    let at_ms = ev.at.as_millis();
+    let delta = at_ms.saturating_sub(last_ms);
+    if delta > 0 {
+      let _ = writeln!(out, "Sleep {}", delta);
+    }
+    last_ms = at_ms;
+    let line = match ev.action {
+      KeyAction::Down(k) => ahk_key(k).map(|name| format!("Send {{{} Down}}", name)),
+      KeyAction::Up(k) => ahk_key(k).map(|name| format!("Send {{{} Up}}", name)),
+      KeyAction::MouseMove { x, y } => Some(format!(
+        "MouseMove, {}, {}, 0",
+        x.round() as i32,
+        y.round() as i32
+      )),
+      KeyAction::ButtonDown(b) => ahk_button(b).map(|name| format!("Click, {}, Down", name)),
+      KeyAction::ButtonUp(b) => ahk_button(b).map(|name| format!("Click, {}, Up", name)),
+      KeyAction::Type(c) => Some(format!("Send {{U+{:04X}}}", c as u32)),
+      // AutoHotkey can read controllers but not press their buttons.
+      KeyAction::GamepadDown(_) | KeyAction::GamepadUp(_) => None,
+    };
+    match line {
+      Some(line) => out.push_str(&line),
+      None => {
+        let _ = write!(out, "; unsupported: {:?}", ev.action);
+      }
+    }
+    out.push('\n');
+  }
+  out
+}
+
+/// Render a timeline as a single-track Standard MIDI File: a key's Down becomes a
+/// note-on and its Up a note-off for the note `mapping` gives it. Keys without a
+/// mapping and non-key events are left out. `bpm` only sets the tempo the ticks are
+/// counted in; the notes land at the recorded times either way.
+pub fn export_midi(events: &[TimedEvent], mapping: &HashMap<Key, u8>, bpm: f64) -> Vec<u8> {
+  let bpm = if bpm.is_finite() && bpm > 0.0 {
+    bpm
+  } else {
+    120.0
+  };
+  let ticks_per_ms = bpm * MIDI_TICKS_PER_BEAT as f64 / 60_000.0;
+  let us_per_beat = (60_000_000.0 / bpm).round().clamp(1.0, 0xFF_FFFF as f64) as u32;
+
+  let mut track = vec![TrackEvent {
+    delta: u28::new(0),
+    kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(us_per_beat))),
+  }];
+  let mut last_tick: u64 = 0;
+  for ev in events {
+    let (key, on) = match ev.action {
+      KeyAction::Down(k) => (k, true),
+      KeyAction::Up(k) => (k, false),
+      _ => continue,
+    };
+    let Some(&note) = mapping.get(&key) else {
+      continue;
+    };
+    let (key, vel) = (u7::new(note.min(127)), u7::new(MIDI_VELOCITY));
+    let message = if on {
+      MidiMessage::NoteOn { key, vel }
+    } else {
+      MidiMessage::NoteOff { key, vel }
+    };
+    let tick = (ev.at.as_secs_f64() * 1000.0 * ticks_per_ms).round() as u64;
+    let delta = tick.saturating_sub(last_tick).min(0x0FFF_FFFF) as u32;
+    last_tick = last_tick.max(tick);
+    track.push(TrackEvent {
+      delta: u28::new(delta),
+      kind: TrackEventKind::Midi {
+        channel: u4::new(0),
+        message,
+      },
+    });
+  }
+  track.push(TrackEvent {
+    delta: u28::new(0),
+    kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+  });
+
+  let mut smf = Smf::new(Header::new(
+    Format::SingleTrack,
+    Timing::Metrical(u15::new(MIDI_TICKS_PER_BEAT)),
+  ));
+  smf.tracks.push(track);
+  let mut out = Vec::new();
+  smf
+    .write_std(&mut out)
+    .expect("writing MIDI to a Vec cannot fail");
+  out
+}
+
+/// Draw the key events as a piano-roll PNG of `width` x `height` pixels: one lane per
+/// key in order of first use, hold bars between presses and releases, Down ticks in
+/// green and Up ticks in red, over a time axis labelled in ms. Lanes are unlabelled.
+pub fn render_timeline_png(events: &[TimedEvent], width: u32, height: u32) -> Vec<u8> {
+  let width = width.max(2 * PNG_MARGIN + 1);
+  let height = height.max(PNG_AXIS_HEIGHT + 1);
+  let mut img = RgbImage::from_pixel(width, height, PNG_BACKGROUND);
+
+  let mut lanes: Vec<Key> = Vec::new();
+  for ev in events {
+    if let KeyAction::Down(k) | KeyAction::Up(k) = ev.action
+      && !lanes.contains(&k)
+    {
+      lanes.push(k);
+    }
+  }
+  let end = events.iter().map(|e| e.at).max().unwrap_or_default();
+  let total_ms = (end.as_secs_f64() * 1000.0).max(1.0);
+  let plot_width = f64::from(width - 2 * PNG_MARGIN);
+  let x_at = |at: Duration| PNG_MARGIN + (at.as_secs_f64() * 1000.0 / total_ms * plot_width) as u32;
+  let lane_height = f64::from(height - PNG_AXIS_HEIGHT) / lanes.len().max(1) as f64;
+  let lane_span = |key: &Key| {
+    let lane = lanes.iter().position(|k| k == key).unwrap_or(0) as f64;
+    let top = (lane * lane_height) as u32;
+    let bottom = ((lane + 1.0) * lane_height) as u32;
+    (top + 2, bottom.saturating_sub(2).max(top + 3))
+  };
+
+  for lane in (0..lanes.len()).step_by(2) {
+    let top = (lane as f64 * lane_height) as u32;
+    let bottom = ((lane + 1) as f64 * lane_height) as u32;
+    fill_rect(&mut img, 0, top, width, bottom, PNG_LANE);
+  }
+  let mut held: HashMap<Key, Duration> = HashMap::new();
+  for ev in events {
+    match ev.action {
+      KeyAction::Down(k) => {
+        held.entry(k).or_insert(ev.at);
+      }
+      KeyAction::Up(k) => {
+        if let Some(start) = held.remove(&k) {
+          let (top, bottom) = lane_span(&k);
+          fill_rect(&mut img, x_at(start), top, x_at(ev.at), bottom, PNG_HOLD);
+        }
+      }
+      _ => {}
+    }
+  }
+  for ev in events {
+    let (key, color) = match ev.action {
+      KeyAction::Down(k) => (k, PNG_DOWN),
+      KeyAction::Up(k) => (k, PNG_UP),
+      _ => continue,
+    };
+    let (top, bottom) = lane_span(&key);
+    let x = x_at(ev.at);
+    fill_rect(&mut img, x.saturating_sub(1), top, x + 2, bottom, color);
+  }
+
+  let axis_y = height - PNG_AXIS_HEIGHT;
+  fill_rect(&mut img, 0, axis_y, width, axis_y + 1, PNG_AXIS);
+  let step_ms = tick_step_ms(total_ms, plot_width);
+  let mut tick_ms = 0;
+  while tick_ms as f64 <= total_ms {
+    let x = x_at(Duration::from_millis(tick_ms));
+    fill_rect(&mut img, x, axis_y, x + 1, axis_y + 5, PNG_AXIS);
+    let label = if tick_ms == 0 {
+      "0 ms".to_string()
+    } else {
+      tick_ms.to_string()
+    };
+    // Labels that would run off the right edge are left out.
+    if x + 2 + label.len() as u32 * 4 * PNG_FONT_SCALE <= width {
+      draw_text(&mut img, x + 2, axis_y + 7, &label, PNG_AXIS);
+    }
+    tick_ms += step_ms;
+  }
+
+  let mut out = Vec::new();
+  img
+    .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+    .expect("encoding a PNG to a Vec cannot fail");
+  out
+}
+
+/// Smallest 1-2-5 step in ms whose ticks are at least `PNG_TICK_SPACING` apart.
+fn tick_step_ms(total_ms: f64, plot_width: f64) -> u64 {
+  let px_per_ms = plot_width / total_ms;
+  let mut magnitude = 1;
+  loop {
+    for factor in [1, 2, 5] {
+      let step = factor * magnitude;
+      if step as f64 * px_per_ms >= PNG_TICK_SPACING {
+        return step;
+      }
+    }
+    magnitude *= 10;
+  }
+}
+
+/// Fill `x0..x1` by `y0..y1`, clipped to the image.
+fn fill_rect(img: &mut RgbImage, x0: u32, y0: u32, x1: u32, y1: u32, color: Rgb<u8>) {
+  for y in y0..y1.min(img.height()) {
+    for x in x0..x1.min(img.width()) {
+      img.put_pixel(x, y, color);
+    }
+  }
+}
+
+/// Draw `text` with the 3x5 axis font; characters it lacks are left blank.
+fn draw_text(img: &mut RgbImage, x: u32, y: u32, text: &str, color: Rgb<u8>) {
+  let s = PNG_FONT_SCALE;
+  for (i, c) in text.chars().enumerate() {
+    let rows = glyph(c);
+    let left = x + i as u32 * 4 * s;
+    for (row, bits) in rows.iter().enumerate() {
+      for col in 0..3 {
+        if bits & (0b100 >> col) != 0 {
+          let (px, py) = (left + col * s, y + row as u32 * s);
+          fill_rect(img, px, py, px + s, py + s, color);
+        }
+      }
+    }
+  }
+}
+
+/// Rows of a 3x5 glyph, top first, with the leftmost pixel in the high bit.
+fn glyph(c: char) -> [u8; 5] {
+  match c {
+    '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+    '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+    '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+    '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+    '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+    '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+    '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+    '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+    '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+    '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+    'm' => [0b000, 0b110, 0b111, 0b101, 0b101],
+    's' => [0b000, 0b011, 0b100, 0b001, 0b110],
+    _ => [0; 5],
+  }
+}
+
+fn ahk_key(key: Key) -> Option<String> {
+  let name = match key {
+    Key::Layout(c) => return Some(c.to_string()),
+    // Raw codes recorded on Windows are virtual-key codes.
+    Key::Raw(code) => return Some(format!("vk{:02X}", code)),
+    Key::Space => "Space",
+    Key::Return => "Enter",
+    Key::Backspace => "Backspace",
+    Key::Tab => "Tab",
+    Key::Escape => "Esc",
+    Key::UpArrow => "Up",
+    Key::DownArrow => "Down",
+    Key::LeftArrow => "Left",
+    Key::RightArrow => "Right",
+    Key::Shift => "Shift",
+    Key::Control => "Ctrl",
+    Key::Alt => "Alt",
+    Key::Meta => "LWin",
+    Key::CapsLock => "CapsLock",
+    Key::Delete => "Delete",
+    Key::Home => "Home",
+    Key::End => "End",
+    Key::PageUp => "PgUp",
+    Key::PageDown => "PgDn",
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::Insert => "Insert",
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::Print => "PrintScreen",
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::Pause => "Pause",
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::Numlock => "NumLock",
+    #[cfg(target_os = "linux")]
+    Key::ScrollLock => "ScrollLock",
+    #[cfg(target_os = "windows")]
+    Key::Scroll => "ScrollLock",
+    Key::F1 => "F1",
+    Key::F2 => "F2",
+    Key::F3 => "F3",
+    Key::F4 => "F4",
+    Key::F5 => "F5",
+    Key::F6 => "F6",
+    Key::F7 => "F7",
+    Key::F8 => "F8",
+    Key::F9 => "F9",
+    Key::F10 => "F10",
+    Key::F11 => "F11",
+    Key::F12 => "F12",
+    _ => return None,
+  };
+  Some(name.to_string())
+}
+
+fn ahk_button(button: MouseButton) -> Option<&'static str> {
+  match button {
+    MouseButton::Left => Some("Left"),
+    MouseButton::Right => Some("Right"),
+    MouseButton::Middle => Some("Middle"),
+    _ => None,
+  }
+}
@@ -0,0 +1,70 @@
+//! Controller input source built on gilrs. Button presses and releases are recorded
+//! as `KeyAction::GamepadDown` / `GamepadUp`; sticks and analog trigger travel are not.
+
+use crate::schema::{GamepadButton, KeyAction};
+use crate::state::AppState;
+use gilrs::{Button, EventType, Gilrs};
+use std::thread;
+use std::time::Duration;
+
+/// How often the controller event queue is drained.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Start a background thread that feeds controller buttons into `state`'s recording.
+/// Logs and gives up if no gamepad backend is available.
+pub fn spawn_listener(state: AppState) {
+  thread::spawn(move || {
+    let mut gilrs = match Gilrs::new() {
+      Ok(gilrs) => gilrs,
+      Err(e) => {
+        state.log(format!("Gamepad input unavailable: {}", e));
+        return;
+      }
+    };
+    for (_, pad) in gilrs.gamepads() {
+      state.log(format!("Gamepad connected: {}", pad.name()));
+    }
+    loop {
+      while let Some(event) = gilrs.next_event() {
+        let action = match event.event {
+          EventType::ButtonPressed(button, _) => convert_button(button).map(KeyAction::GamepadDown),
+          EventType::ButtonReleased(button, _) => convert_button(button).map(KeyAction::GamepadUp),
+          EventType::Connected => {
+            let name = gilrs.gamepad(event.id).name().to_string();
+            state.log(format!("Gamepad connected: {}", name));
+            None
+          }
+          _ => None,
+        };
+        if let Some(action) = action {
+          state.record_gamepad(action);
+        }
+      }
+      thread::sleep(POLL_INTERVAL);
+    }
+  });
+}
+
+fn convert_button(button: Button) -> Option<GamepadButton> {
+  let mapped = match button {
+    Button::South => GamepadButton::South,
+    Button::East => GamepadButton::East,
+    Button::North => GamepadButton::North,
+    Button::West => GamepadButton::West,
+    Button::LeftTrigger => GamepadButton::LeftBumper,
+    Button::RightTrigger => GamepadButton::RightBumper,
+    Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+    Button::RightTrigger2 => GamepadButton::RightTrigger,
+    Button::Select => GamepadButton::Select,
+    Button::Start => GamepadButton::Start,
+    Button::Mode => GamepadButton::Mode,
+    Button::LeftThumb => GamepadButton::LeftThumb,
+    Button::RightThumb => GamepadButton::RightThumb,
+    Button::DPadUp => GamepadButton::DPadUp,
+    Button::DPadDown => GamepadButton::DPadDown,
+    Button::DPadLeft => GamepadButton::DPadLeft,
+    Button::DPadRight => GamepadButton::DPadRight,
+    _ => return None,
+  };
+  Some(mapped)
+}
@@ -0,0 +1,236 @@
+//! Keyboard device selection. rdev sees every keyboard at once; with the
+//! `device-filter` feature on Linux, key input can instead be read through evdev from
+//! one chosen device node, so a macro pad or second keyboard stays out of recordings.
+//! Elsewhere the device list is empty and every keyboard is recorded.
+
+use crate::state::AppState;
+#[cfg(all(feature = "device-filter", target_os = "linux"))]
+use rdev::Key;
+use std::path::PathBuf;
+
+/// A keyboard the recorder can be limited to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputDevice {
+  /// Device node, e.g. `/dev/input/event3`.
+  pub path: PathBuf,
+  pub name: String,
+}
+
+/// Whether this build can record from a single device.
+pub const SUPPORTED: bool = cfg!(all(feature = "device-filter", target_os = "linux"));
+
+/// Keyboards that can be opened, by device node. Empty where enumeration isn't
+/// available, or when `/dev/input` isn't readable by this user.
+#[cfg(all(feature = "device-filter", target_os = "linux"))]
+pub fn list() -> Vec<InputDevice> {
+  let mut devices: Vec<InputDevice> = evdev::enumerate()
+    .filter(|(_, device)| {
+      device
+        .supported_keys()
+        .is_some_and(|keys| keys.iter().any(|k| is_keyboard_code(k.code())))
+    })
+    .map(|(path, device)| InputDevice {
+      name: device.name().unwrap_or("Unnamed device").to_string(),
+      path,
+    })
+    .collect();
+  devices.sort_by(|a, b| a.path.cmp(&b.path));
+  devices
+}
+
+#[cfg(not(all(feature = "device-filter", target_os = "linux")))]
+pub fn list() -> Vec<InputDevice> {
+  Vec::new()
+}
+
+/// Start a thread that records `path`'s keys into `state` for the selection made at
+/// `epoch`. It ends once `state` selects another device; if the device can't be
+/// opened or goes away, the selection is cleared so recording falls back to every
+/// keyboard.
+#[cfg(all(feature = "device-filter", target_os = "linux"))]
+pub fn spawn_listener(state: AppState, path: PathBuf, epoch: usize) {
+  use evdev::{Device, InputEventKind};
+  use rdev::{Event, EventType};
+
+  std::thread::spawn(move || {
+    let mut device = match Device::open(&path) {
+      Ok(device) => device,
+      Err(e) => {
+        state.log(format!("Can't open {}: {}", path.display(), e));
+        state.forget_input_device(epoch);
+        return;
+      }
+    };
+    let name = device.name().unwrap_or("Unnamed device").to_string();
+    state.log(format!(
+      "Recording keys only from {} ({})",
+      name,
+      path.display()
+    ));
+    loop {
+      let events = match device.fetch_events() {
+        Ok(events) => events,
+        Err(e) => {
+          state.log(format!("Lost {} ({}): {}", name, path.display(), e));
+          state.forget_input_device(epoch);
+          return;
+        }
+      };
+      for event in events {
+        if state.input_device_epoch() != epoch {
+          return;
+        }
+        let InputEventKind::Key(key) = event.kind() else {
+          continue;
+        };
+        if !is_keyboard_code(key.code()) {
+          continue;
+        }
+        // 1 is a press and 2 an autorepeat, which X also reports as a press.
+        let key = key_from_code(key.code());
+        let event_type = match event.value() {
+          0 => EventType::KeyRelease(key),
+          _ => EventType::KeyPress(key),
+        };
+        let event = Event {
+          time: event.timestamp(),
+          name: None,
+          event_type,
+        };
+        crate::state::record_input(&state, event);
+      }
+    }
+  });
+}
+
+#[cfg(not(all(feature = "device-filter", target_os = "linux")))]
+pub fn spawn_listener(state: AppState, path: PathBuf, epoch: usize) {
+  state.log(format!(
+    "Can't record only from {}: not supported by this build",
+    path.display()
+  ));
+  state.forget_input_device(epoch);
+}
+
+/// Mouse and joystick buttons sit at 0x100-0x15f; those stay with the pointer listener.
+#[cfg(all(feature = "device-filter", target_os = "linux"))]
+fn is_keyboard_code(code: u16) -> bool {
+  !(0x100..0x160).contains(&code)
+}
+
+/// The rdev key X11 reports for a Linux input-event code. X keycodes are the evdev
+/// code plus 8, so keys rdev has no name for come out as the same `Unknown` code.
+#[cfg(all(feature = "device-filter", target_os = "linux"))]
+fn key_from_code(code: u16) -> Key {
+  EVDEV_KEYS
+    .iter()
+    .find(|(c, _)| *c == code)
+    .map_or(Key::Unknown(u32::from(code) + 8), |&(_, key)| key)
+}
+
+#[cfg(all(feature = "device-filter", target_os = "linux"))]
+const EVDEV_KEYS: &[(u16, Key)] = &[
+  (1, Key::Escape),
+  (2, Key::Num1),
+  (3, Key::Num2),
+  (4, Key::Num3),
+  (5, Key::Num4),
+  (6, Key::Num5),
+  (7, Key::Num6),
+  (8, Key::Num7),
+  (9, Key::Num8),
+  (10, Key::Num9),
+  (11, Key::Num0),
+  (12, Key::Minus),
+  (13, Key::Equal),
+  (14, Key::Backspace),
+  (15, Key::Tab),
+  (16, Key::KeyQ),
+  (17, Key::KeyW),
+  (18, Key::KeyE),
+  (19, Key::KeyR),
+  (20, Key::KeyT),
+  (21, Key::KeyY),
+  (22, Key::KeyU),
+  (23, Key::KeyI),
+  (24, Key::KeyO),
+  (25, Key::KeyP),
+  (26, Key::LeftBracket),
+  (27, Key::RightBracket),
+  (28, Key::Return),
+  (29, Key::ControlLeft),
+  (30, Key::KeyA),
+  (31, Key::KeyS),
+  (32, Key::KeyD),
+  (33, Key::KeyF),
+  (34, Key::KeyG),
+  (35, Key::KeyH),
+  (36, Key::KeyJ),
+  (37, Key::KeyK),
+  (38, Key::KeyL),
+  (39, Key::SemiColon),
+  (40, Key::Quote),
+  (41, Key::BackQuote),
+  (42, Key::ShiftLeft),
+  (43, Key::BackSlash),
+  (44, Key::KeyZ),
+  (45, Key::KeyX),
+  (46, Key::KeyC),
+  (47, Key::KeyV),
+  (48, Key::KeyB),
+  (49, Key::KeyN),
+  (50, Key::KeyM),
+  (51, Key::Comma),
+  (52, Key::Dot),
+  (53, Key::Slash),
+  (54, Key::ShiftRight),
+  (55, Key::KpMultiply),
+  (56, Key::Alt),
+  (57, Key::Space),
+  (58, Key::CapsLock),
+  (59, Key::F1),
+  (60, Key::F2),
+  (61, Key::F3),
+  (62, Key::F4),
+  (63, Key::F5),
+  (64, Key::F6),
+  (65, Key::F7),
+  (66, Key::F8),
+  (67, Key::F9),
+  (68, Key::F10),
+  (69, Key::NumLock),
+  (70, Key::ScrollLock),
+  (71, Key::Kp7),
+  (72, Key::Kp8),
+  (73, Key::Kp9),
+  (74, Key::KpMinus),
+  (75, Key::Kp4),
+  (76, Key::Kp5),
+  (77, Key::Kp6),
+  (78, Key::KpPlus),
+  (79, Key::Kp1),
+  (80, Key::Kp2),
+  (81, Key::Kp3),
+  (82, Key::Kp0),
+  (83, Key::KpDelete),
+  (86, Key::IntlBackslash),
+  (87, Key::F11),
+  (88, Key::F12),
+  (96, Key::KpReturn),
+  (97, Key::ControlRight),
+  (98, Key::KpDivide),
+  (99, Key::PrintScreen),
+  (100, Key::AltGr),
+  (102, Key::Home),
+  (103, Key::UpArrow),
+  (104, Key::PageUp),
+  (105, Key::LeftArrow),
+  (106, Key::RightArrow),
+  (107, Key::End),
+  (108, Key::DownArrow),
+  (109, Key::PageDown),
+  (110, Key::Insert),
+  (111, Key::Delete),
+  (119, Key::Pause),
+  (125, Key::MetaLeft),
+];
@@ -0,0 +1,391 @@
+use crate::persist::string_to_key;
+use rdev::Key;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Every rdev key a layout file may name, matched by its variant name.
+const RDEV_KEYS: &[Key] = &[
+  Key::Alt,
+  Key::AltGr,
+  Key::Backspace,
+  Key::CapsLock,
+  Key::ControlLeft,
+  Key::ControlRight,
+  Key::Delete,
+  Key::DownArrow,
+  Key::End,
+  Key::Escape,
+  Key::F1,
+  Key::F2,
+  Key::F3,
+  Key::F4,
+  Key::F5,
+  Key::F6,
+  Key::F7,
+  Key::F8,
+  Key::F9,
+  Key::F10,
+  Key::F11,
+  Key::F12,
+  Key::Home,
+  Key::LeftArrow,
+  Key::MetaLeft,
+  Key::MetaRight,
+  Key::PageDown,
+  Key::PageUp,
+  Key::Return,
+  Key::RightArrow,
+  Key::ShiftLeft,
+  Key::ShiftRight,
+  Key::Space,
+  Key::Tab,
+  Key::UpArrow,
+  Key::PrintScreen,
+  Key::ScrollLock,
+  Key::Pause,
+  Key::NumLock,
+  Key::BackQuote,
+  Key::Num1,
+  Key::Num2,
+  Key::Num3,
+  Key::Num4,
+  Key::Num5,
+  Key::Num6,
+  Key::Num7,
+  Key::Num8,
+  Key::Num9,
+  Key::Num0,
+  Key::Minus,
+  Key::Equal,
+  Key::KeyQ,
+  Key::KeyW,
+  Key::KeyE,
+  Key::KeyR,
+  Key::KeyT,
+  Key::KeyY,
+  Key::KeyU,
+  Key::KeyI,
+  Key::KeyO,
+  Key::KeyP,
+  Key::LeftBracket,
+  Key::RightBracket,
+  Key::KeyA,
+  Key::KeyS,
+  Key::KeyD,
+  Key::KeyF,
+  Key::KeyG,
+  Key::KeyH,
+  Key::KeyJ,
+  Key::KeyK,
+  Key::KeyL,
+  Key::SemiColon,
+  Key::Quote,
+  Key::BackSlash,
+  Key::IntlBackslash,
+  Key::KeyZ,
+  Key::KeyX,
+  Key::KeyC,
+  Key::KeyV,
+  Key::KeyB,
+  Key::KeyN,
+  Key::KeyM,
+  Key::Comma,
+  Key::Dot,
+  Key::Slash,
+  Key::Insert,
+  Key::KpReturn,
+  Key::KpMinus,
+  Key::KpPlus,
+  Key::KpMultiply,
+  Key::KpDivide,
+  Key::Kp0,
+  Key::Kp1,
+  Key::Kp2,
+  Key::Kp3,
+  Key::Kp4,
+  Key::Kp5,
+  Key::Kp6,
+  Key::Kp7,
+  Key::Kp8,
+  Key::Kp9,
+  Key::KpDelete,
+  Key::Function,
+];
+
+/// The rdev key whose variant name is `name`, e.g. `F9` or `KeyQ`.
+pub fn rdev_key_by_name(name: &str) -> Option<Key> {
+  RDEV_KEYS.iter().copied().find(|k| format!("{:?}", k) == name)
+}
+
+/// Maps the keys reported by rdev to the keys enigo sends, so recordings come out
+/// right on non-US layouts.
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+  entries: HashMap<Key, enigo::Key>,
+}
+
+#[derive(Debug)]
+pub enum KeyMapError {
+  Io(io::Error),
+  Parse(serde_json::Error),
+  /// A name that is not an rdev key, or a value that is not a key this build can send.
+  UnknownKey(String),
+}
+
+impl fmt::Display for KeyMapError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      KeyMapError::Io(e) => write!(f, "{}", e),
+      KeyMapError::Parse(e) => write!(f, "unreadable layout file: {}", e),
+      KeyMapError::UnknownKey(name) => write!(f, "unknown key \"{}\"", name),
+    }
+  }
+}
+
+impl std::error::Error for KeyMapError {}
+
+impl From<io::Error> for KeyMapError {
+  fn from(e: io::Error) -> Self {
+    KeyMapError::Io(e)
+  }
+}
+
+impl From<serde_json::Error> for KeyMapError {
+  fn from(e: serde_json::Error) -> Self {
+    KeyMapError::Parse(e)
+  }
+}
+
+impl Default for KeyMap {
+  fn default() -> Self {
+    Self::us()
+  }
+}
+
+impl KeyMap {
+  /// The built-in US QWERTY mapping.
+  pub fn us() -> Self {
+    let entries = RDEV_KEYS
+      .iter()
+      .filter_map(|&k| us_key(k).map(|mapped| (k, mapped)))
+      .collect();
+    Self { entries }
+  }
+
+  /// Parse a JSON object such as `{"KeyQ": "a", "KeyA": "q"}`. Names are rdev key
+  /// variants, values use the save-file key names; entries override the US mapping.
+  pub fn from_json(data: &str) -> Result<Self, KeyMapError> {
+    let overrides: HashMap<String, String> = serde_json::from_str(data)?;
+    let mut map = Self::us();
+    for (name, value) in overrides {
+      let key = rdev_key_by_name(&name).ok_or(KeyMapError::UnknownKey(name))?;
+      let mapped = string_to_key(&value).ok_or(KeyMapError::UnknownKey(value))?;
+      map.entries.insert(key, mapped);
+    }
+    Ok(map)
+  }
+
+  pub fn load(path: &Path) -> Result<Self, KeyMapError> {
+    Self::from_json(&fs::read_to_string(path)?)
+  }
+
+  /// Keys rdev can't name are passed through as their raw platform code.
+  pub fn get(&self, key: Key) -> Option<enigo::Key> {
+    if let Key::Unknown(code) = key {
+      return u16::try_from(code).ok().map(enigo::Key::Raw);
+    }
+    self.entries.get(&key).copied()
+  }
+}
+
+/// The built-in US layout: rdev key -> enigo key.
+fn us_key(key: Key) -> Option<enigo::Key> {
+  // rdev Key -> enigo Key mapping. Return None if unknown to avoid sending spaces.
+  let mapped = match key {
+    Key::KeyA => enigo::Key::Layout('a'),
+    Key::KeyB => enigo::Key::Layout('b'),
+    Key::KeyC => enigo::Key::Layout('c'),
+    Key::KeyD => enigo::Key::Layout('d'),
+    Key::KeyE => enigo::Key::Layout('e'),
+    Key::KeyF => enigo::Key::Layout('f'),
+    Key::KeyG => enigo::Key::Layout('g'),
+    Key::KeyH => enigo::Key::Layout('h'),
+    Key::KeyI => enigo::Key::Layout('i'),
+    Key::KeyJ => enigo::Key::Layout('j'),
+    Key::KeyK => enigo::Key::Layout('k'),
+    Key::KeyL => enigo::Key::Layout('l'),
+    Key::KeyM => enigo::Key::Layout('m'),
+    Key::KeyN => enigo::Key::Layout('n'),
+    Key::KeyO => enigo::Key::Layout('o'),
+    Key::KeyP => enigo::Key::Layout('p'),
+    Key::KeyQ => enigo::Key::Layout('q'),
+    Key::KeyR => enigo::Key::Layout('r'),
+    Key::KeyS => enigo::Key::Layout('s'),
+    Key::KeyT => enigo::Key::Layout('t'),
+    Key::KeyU => enigo::Key::Layout('u'),
+    Key::KeyV => enigo::Key::Layout('v'),
+    Key::KeyW => enigo::Key::Layout('w'),
+    Key::KeyX => enigo::Key::Layout('x'),
+    Key::KeyY => enigo::Key::Layout('y'),
+    Key::KeyZ => enigo::Key::Layout('z'),
+    Key::Num0 => enigo::Key::Layout('0'),
+    Key::Num1 => enigo::Key::Layout('1'),
+    Key::Num2 => enigo::Key::Layout('2'),
+    Key::Num3 => enigo::Key::Layout('3'),
+    Key::Num4 => enigo::Key::Layout('4'),
+    Key::Num5 => enigo::Key::Layout('5'),
+    Key::Num6 => enigo::Key::Layout('6'),
+    Key::Num7 => enigo::Key::Layout('7'),
+    Key::Num8 => enigo::Key::Layout('8'),
+    Key::Num9 => enigo::Key::Layout('9'),
+    Key::SemiColon => enigo::Key::Layout(';'),
+    Key::Slash => enigo::Key::Layout('/'),
+    Key::Comma => enigo::Key::Layout(','),
+    Key::Dot => enigo::Key::Layout('.'),
+    Key::Minus => enigo::Key::Layout('-'),
+    Key::Equal => enigo::Key::Layout('='),
+    Key::LeftBracket => enigo::Key::Layout('['),
+    Key::RightBracket => enigo::Key::Layout(']'),
+    Key::BackSlash => enigo::Key::Layout('\\'),
+    Key::Quote => enigo::Key::Layout('\''),
+    Key::BackQuote => enigo::Key::Layout('`'),
+    Key::F1 => enigo::Key::F1,
+    Key::F2 => enigo::Key::F2,
+    Key::F3 => enigo::Key::F3,
+    Key::F4 => enigo::Key::F4,
+    Key::F5 => enigo::Key::F5,
+    Key::F6 => enigo::Key::F6,
+    Key::F7 => enigo::Key::F7,
+    Key::F8 => enigo::Key::F8,
+    Key::F9 => enigo::Key::F9,
+    Key::F10 => enigo::Key::F10,
+    Key::F11 => enigo::Key::F11,
+    Key::F12 => enigo::Key::F12,
+    Key::Space => enigo::Key::Space,
+    Key::Return => enigo::Key::Return,
+    Key::Backspace => enigo::Key::Backspace,
+    Key::Tab => enigo::Key::Tab,
+    Key::Escape => enigo::Key::Escape,
+    Key::UpArrow => enigo::Key::UpArrow,
+    Key::DownArrow => enigo::Key::DownArrow,
+    Key::LeftArrow => enigo::Key::LeftArrow,
+    Key::RightArrow => enigo::Key::RightArrow,
+    Key::ShiftLeft | Key::ShiftRight => enigo::Key::Shift,
+    Key::ControlLeft | Key::ControlRight => enigo::Key::Control,
+    Key::Alt | Key::AltGr => enigo::Key::Alt,
+    Key::MetaLeft | Key::MetaRight => enigo::Key::Meta,
+    Key::CapsLock => enigo::Key::CapsLock,
+    Key::Delete | Key::KpDelete => enigo::Key::Delete,
+    Key::Home => enigo::Key::Home,
+    Key::End => enigo::Key::End,
+    Key::PageUp => enigo::Key::PageUp,
+    Key::PageDown => enigo::Key::PageDown,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::Insert => enigo::Key::Insert,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::PrintScreen => enigo::Key::Print,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::Pause => enigo::Key::Pause,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Key::NumLock => enigo::Key::Numlock,
+    #[cfg(target_os = "linux")]
+    Key::ScrollLock => enigo::Key::ScrollLock,
+    #[cfg(target_os = "windows")]
+    Key::ScrollLock => enigo::Key::Scroll,
+    // Keypad keys send the character they type; enigo only names them on Windows.
+    Key::Kp0 => enigo::Key::Layout('0'),
+    Key::Kp1 => enigo::Key::Layout('1'),
+    Key::Kp2 => enigo::Key::Layout('2'),
+    Key::Kp3 => enigo::Key::Layout('3'),
+    Key::Kp4 => enigo::Key::Layout('4'),
+    Key::Kp5 => enigo::Key::Layout('5'),
+    Key::Kp6 => enigo::Key::Layout('6'),
+    Key::Kp7 => enigo::Key::Layout('7'),
+    Key::Kp8 => enigo::Key::Layout('8'),
+    Key::Kp9 => enigo::Key::Layout('9'),
+    Key::KpPlus => enigo::Key::Layout('+'),
+    Key::KpMinus => enigo::Key::Layout('-'),
+    Key::KpMultiply => enigo::Key::Layout('*'),
+    Key::KpDivide => enigo::Key::Layout('/'),
+    Key::KpReturn => enigo::Key::Return,
+    Key::IntlBackslash => enigo::Key::Layout('\\'),
+    _ => return None,
+  };
+  Some(mapped)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use enigo::Key as EKey;
+
+  #[test]
+  fn us_key_maps_function_and_punctuation_keys() {
+    let table = [
+      (Key::F1, Some(EKey::F1)),
+      (Key::F2, Some(EKey::F2)),
+      (Key::F3, Some(EKey::F3)),
+      (Key::F4, Some(EKey::F4)),
+      (Key::F5, Some(EKey::F5)),
+      (Key::F6, Some(EKey::F6)),
+      (Key::F7, Some(EKey::F7)),
+      (Key::F8, Some(EKey::F8)),
+      (Key::F9, Some(EKey::F9)),
+      (Key::F10, Some(EKey::F10)),
+      (Key::F11, Some(EKey::F11)),
+      (Key::F12, Some(EKey::F12)),
+      (Key::SemiColon, Some(EKey::Layout(';'))),
+      (Key::Slash, Some(EKey::Layout('/'))),
+      (Key::Comma, Some(EKey::Layout(','))),
+      (Key::Dot, Some(EKey::Layout('.'))),
+      (Key::Minus, Some(EKey::Layout('-'))),
+      (Key::Equal, Some(EKey::Layout('='))),
+      (Key::LeftBracket, Some(EKey::Layout('['))),
+      (Key::RightBracket, Some(EKey::Layout(']'))),
+      (Key::BackSlash, Some(EKey::Layout('\\'))),
+      (Key::Quote, Some(EKey::Layout('\''))),
+      (Key::BackQuote, Some(EKey::Layout('`'))),
+      (Key::KeyA, Some(EKey::Layout('a'))),
+      (Key::Num0, Some(EKey::Layout('0'))),
+      (Key::Space, Some(EKey::Space)),
+      (Key::ShiftRight, Some(EKey::Shift)),
+      (Key::Function, None),
+    ];
+    for (key, expected) in table {
+      assert_eq!(us_key(key), expected, "{:?}", key);
+    }
+  }
+
+  #[test]
+  fn us_key_maps_editing_and_keypad_keys() {
+    let table = [
+      (Key::CapsLock, EKey::CapsLock),
+      (Key::Delete, EKey::Delete),
+      (Key::Home, EKey::Home),
+      (Key::End, EKey::End),
+      (Key::PageUp, EKey::PageUp),
+      (Key::PageDown, EKey::PageDown),
+      (Key::MetaLeft, EKey::Meta),
+      (Key::MetaRight, EKey::Meta),
+      (Key::Kp0, EKey::Layout('0')),
+      (Key::Kp9, EKey::Layout('9')),
+      (Key::KpPlus, EKey::Layout('+')),
+      (Key::KpMinus, EKey::Layout('-')),
+      (Key::KpMultiply, EKey::Layout('*')),
+      (Key::KpDivide, EKey::Layout('/')),
+      (Key::KpReturn, EKey::Return),
+      (Key::KpDelete, EKey::Delete),
+      #[cfg(any(target_os = "windows", target_os = "linux"))]
+      (Key::Insert, EKey::Insert),
+      #[cfg(any(target_os = "windows", target_os = "linux"))]
+      (Key::PrintScreen, EKey::Print),
+    ];
+    for (key, expected) in table {
+      assert_eq!(us_key(key), Some(expected), "{:?}", key);
+      assert_eq!(KeyMap::us().get(key), Some(expected), "{:?}", key);
+    }
+  }
+}
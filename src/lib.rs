@@ -0,0 +1,103 @@
+//! Record and replay keyboard and mouse macros, and controller buttons with the
+//! `gamepad` feature.
+//!
+//! The egui recorder app is the `auto-note` binary, built with the `gui` feature. This
+//! library holds the recording, playback and save-file logic it is built on, without
+//! any GUI dependency.
+//!
+//! ```no_run
+//! use auto_note::{KeyAction, PlaybackOptions, TimedEvent};
+//! use enigo::Key;
+//! use std::time::Duration;
+//!
+//! let tap = |ms, action| TimedEvent { at: Duration::from_millis(ms), action };
+//! let events = vec![
+//!   tap(0, KeyAction::Down(Key::Layout('a'))),
+//!   tap(80, KeyAction::Up(Key::Layout('a'))),
+//!   tap(150, KeyAction::Down(Key::Space)),
+//!   tap(230, KeyAction::Up(Key::Space)),
+//! ];
+//! if let Some(report) = auto_note::play(events, PlaybackOptions::default()) {
+//!   println!("{}", report);
+//! }
+//! ```
+
+pub mod chords;
+#[cfg(feature = "command-server")]
+pub mod command_server;
+pub mod csv;
+pub mod export;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+pub mod input_device;
+pub mod keymap;
+pub mod logbuf;
+pub mod macro_play;
+pub mod persist;
+pub mod schema;
+pub mod settings;
+pub mod state;
+pub mod stats;
+pub mod text_timeline;
+pub mod transform;
+pub mod validate;
+
+pub use macro_play::{GapMode, PlaybackOptions, PlaybackReport, TimingMode};
+pub use persist::LoadError;
+pub use schema::{GamepadButton, KeyAction, TimedEvent};
+pub use state::{AppState, Sample};
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Shared by every `record` call; rdev allows only one global listener per process.
+fn recorder() -> &'static AppState {
+  static RECORDER: OnceLock<AppState> = OnceLock::new();
+  RECORDER.get_or_init(|| {
+    let state = AppState::new();
+    state.spawn_global_listener();
+    state
+  })
+}
+
+/// Capture global keyboard and mouse input for `duration`. The first call starts a
+/// process-wide listener that keeps running afterwards.
+pub fn record(duration: Duration) -> Vec<TimedEvent> {
+  let state = recorder();
+  state.start_recording();
+  thread::sleep(duration);
+  state.recording.store(false, Ordering::SeqCst);
+  state.current_events.lock().unwrap().clone()
+}
+
+/// Play `events` and block until playback ends. Returns the run's timing accuracy,
+/// or `None` if nothing was sent.
+pub fn play(events: Vec<TimedEvent>, opts: PlaybackOptions) -> Option<PlaybackReport> {
+  let report = Arc::new(Mutex::new(None));
+  let handle = macro_play::play_timeline_async(
+    events,
+    Arc::new(AtomicBool::new(false)),
+    Arc::new(AtomicBool::new(false)),
+    opts,
+    Arc::new(Mutex::new(Default::default())),
+    report.clone(),
+  );
+  let _ = handle.join();
+  *report.lock().unwrap()
+}
+
+/// Write `samples` to `path` with microsecond timestamps. Returns how many events
+/// were skipped because their key has no stable name.
+pub fn save(path: &Path, samples: &[Sample]) -> io::Result<usize> {
+  persist::save_file(path, samples, true)
+}
+
+/// Read samples from a save file of any version. Events whose key this build can't
+/// map are dropped.
+pub fn load(path: &Path) -> Result<Vec<Sample>, LoadError> {
+  persist::load_file(path).map(|loaded| loaded.samples)
+}
@@ -0,0 +1,15 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Lines kept for the in-app log panel; older lines are dropped first.
+pub const LOG_CAPACITY: usize = 500;
+
+pub type LogBuffer = Arc<Mutex<VecDeque<String>>>;
+
+pub fn push_line(buf: &LogBuffer, msg: String) {
+  let mut lines = buf.lock().unwrap();
+  while lines.len() >= LOG_CAPACITY {
+    lines.pop_front();
+  }
+  lines.push_back(msg);
+}
@@ -1,54 +1,429 @@
-use crate::schema::{KeyAction, TimedEvent};
-use enigo::{Enigo, KeyboardControllable};
+use crate::logbuf::{self, LogBuffer};
+use crate::schema::{GamepadButton, KeyAction, TimedEvent};
+use crate::transform::{self, TieOrder};
+use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::{
   atomic::{AtomicBool, Ordering},
-  Arc,
+  Arc, Mutex, MutexGuard, OnceLock,
 };
 use std::thread;
 use std::time::{Duration, Instant};
 
-/// Play recorded key timeline asynchronously.
+/// How often a paused playback checks whether it was resumed or stopped.
+const PAUSE_POLL: Duration = Duration::from_millis(10);
+
+/// How `wait_until` trades timing precision for CPU time.
+///
+/// Lateness measured on a Linux desktop over 1000 waits of 3-12 ms (mean / p99):
+/// Precise 0.02 / 0.3 ms, Balanced 0.1 / 0.5 ms, PowerSaver 0.1 / 0.45 ms. Balanced
+/// only spins when events are under 2 ms apart. Windows sleeps in whole timer ticks
+/// (1-15.6 ms), so Balanced and PowerSaver are correspondingly coarser there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimingMode {
+  /// Sleep to 200 µs before the deadline, spin the last 500 µs.
+  #[default]
+  Precise,
+  /// Sleep straight to the deadline, spin only for waits under 2 ms.
+  Balanced,
+  /// Never spin.
+  PowerSaver,
+}
+
+impl TimingMode {
+  /// Remaining waits at or below this are spun instead of slept.
+  fn spin_threshold(self) -> Duration {
+    match self {
+      TimingMode::Precise => Duration::from_micros(500),
+      TimingMode::Balanced => Duration::from_millis(2),
+      TimingMode::PowerSaver => Duration::ZERO,
+    }
+  }
+
+  /// Subtracted from each sleep so the OS waking us late doesn't overshoot.
+  fn sleep_guard(self) -> Duration {
+    match self {
+      TimingMode::Precise => Duration::from_micros(200),
+      TimingMode::Balanced | TimingMode::PowerSaver => Duration::ZERO,
+    }
+  }
+}
+
+/// What happens to the rest of the schedule when `min_gap_ms` delays an event.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapMode {
+  /// Later events move back by the same amount, keeping their spacing.
+  #[default]
+  Shift,
+  /// Only the too-early event moves; later events keep their original times.
+  Clamp,
+}
+
+/// Knobs for a single playback run.
+#[derive(Clone, Debug)]
+pub struct PlaybackOptions {
+  pub offset_ms: i64,
+  /// Number of passes over the timeline; 0 loops until stopped.
+  pub repeat: u32,
+  /// Random pause in ms, drawn from `min..=max`, between passes.
+  pub loop_gap_ms: (u64, u64),
+  /// Standard deviation of the random per-event timing jitter in ms; 0 disables it.
+  pub jitter_ms: f64,
+  /// Seed for the jitter RNG so a run can be reproduced.
+  pub seed: u64,
+  pub timing: TimingMode,
+  /// Schedule and log every event but send nothing to the OS.
+  pub dry_run: bool,
+  /// Keep at least this many ms between sent key and button events; 0 disables it.
+  pub min_gap_ms: u64,
+  pub gap_mode: GapMode,
+  /// Extra offset in ms for the presses and releases of individual keys.
+  pub key_offsets_ms: HashMap<Key, i64>,
+  /// Keys to send in place of the recorded ones; unmapped keys pass through.
+  pub key_remap: HashMap<Key, Key>,
+  /// Recorded keys whose presses and releases are not sent; matched before remapping.
+  pub muted_keys: HashSet<Key>,
+  /// Key sent for each controller button; buttons without one are skipped.
+  pub gamepad_keys: HashMap<GamepadButton, Key>,
+  /// Keys held down for the whole run, pressed before the first event and released
+  /// after the last. Recorded events for these keys are dropped so they stay held.
+  pub wrap_modifiers: Vec<Key>,
+  /// Order of presses and releases that share a timestamp.
+  pub tie_order: TieOrder,
+}
+
+impl Default for PlaybackOptions {
+  /// A single pass with no offset or jitter.
+  fn default() -> Self {
+    Self {
+      offset_ms: 0,
+      repeat: 1,
+      loop_gap_ms: (0, 0),
+      jitter_ms: 0.0,
+      seed: 0,
+      timing: TimingMode::default(),
+      dry_run: false,
+      min_gap_ms: 0,
+      gap_mode: GapMode::default(),
+      key_offsets_ms: HashMap::new(),
+      key_remap: HashMap::new(),
+      muted_keys: HashSet::new(),
+      gamepad_keys: HashMap::new(),
+      wrap_modifiers: Vec::new(),
+      tie_order: TieOrder::default(),
+    }
+  }
+}
+
+/// How far actual send times strayed from the schedule in a finished run, and how long
+/// sending took.
+#[derive(Clone, Copy, Debug)]
+pub struct PlaybackReport {
+  pub events: usize,
+  pub mean_ms: f64,
+  pub max_ms: f64,
+  /// From the playback request until the input sender was ready for the first event.
+  pub startup_ms: f64,
+  /// Time spent inside the OS send call per event; 0 for a dry run.
+  pub send_mean_ms: f64,
+  pub send_max_ms: f64,
+  /// Jitter seed the run used; pass it to `PlaybackOptions::seed` to repeat the run.
+  pub seed: u64,
+}
+
+impl PlaybackReport {
+  /// Summarize absolute timing errors and per-event send times in ms; `None` if
+  /// nothing was sent.
+  fn from_errors(
+    errors_ms: &[f64],
+    sends_ms: &[f64],
+    startup: Duration,
+    seed: u64,
+  ) -> Option<Self> {
+    if errors_ms.is_empty() {
+      return None;
+    }
+    Some(Self {
+      events: errors_ms.len(),
+      mean_ms: mean(errors_ms),
+      max_ms: errors_ms.iter().copied().fold(0.0, f64::max),
+      startup_ms: startup.as_secs_f64() * 1000.0,
+      send_mean_ms: mean(sends_ms),
+      send_max_ms: sends_ms.iter().copied().fold(0.0, f64::max),
+      seed,
+    })
+  }
+}
+
+fn mean(values: &[f64]) -> f64 {
+  if values.is_empty() {
+    return 0.0;
+  }
+  values.iter().sum::<f64>() / values.len() as f64
+}
+
+impl fmt::Display for PlaybackReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "last run: mean {:.1}ms, max {:.1}ms, seed {}; send mean {:.2}ms, max {:.2}ms, \
+       startup {:.1}ms",
+      self.mean_ms, self.max_ms, self.seed, self.send_mean_ms, self.send_max_ms, self.startup_ms
+    )
+  }
+}
+
+/// Play recorded key timeline asynchronously. While `paused` is set the schedule is
+/// frozen; time spent paused is not counted against the remaining events.
+/// When the run ends, its timing accuracy is stored in `report`.
 pub fn play_timeline_async(
   events: Vec<TimedEvent>,
   stop: Arc<AtomicBool>,
-  offset_ms: i64,
+  paused: Arc<AtomicBool>,
+  opts: PlaybackOptions,
+  log: LogBuffer,
+  report: Arc<Mutex<Option<PlaybackReport>>>,
 ) -> thread::JoinHandle<()> {
+  let events = drop_keys(events, |k| opts.muted_keys.contains(k));
+  let events = map_gamepad(events, &opts.gamepad_keys);
+  let events = remap_keys(apply_key_offsets(events, &opts.key_offsets_ms), &opts.key_remap);
+  let mut events = drop_keys(events, |k| opts.wrap_modifiers.contains(k));
+  transform::sort_events(&mut events, opts.tie_order);
+  let requested = Instant::now();
   thread::spawn(move || {
-    let start = Instant::now();
-    let mut enigo = Enigo::new();
+    let mut enigo = shared_enigo();
+    let startup = requested.elapsed();
+    let mut held = Held::default();
+    let mut rng = StdRng::seed_from_u64(opts.seed);
+    let mut pass: u32 = 0;
+    let mut errors_ms = Vec::new();
+    let mut sends_ms = Vec::new();
+    if opts.jitter_ms > 0.0 {
+      logbuf::push_line(
+        &log,
+        format!("playback jitter {} ms (seed {})", opts.jitter_ms, opts.seed),
+      );
+    }
+    if !opts.dry_run {
+      for &k in &opts.wrap_modifiers {
+        logbuf::push_line(&log, format!("play: holding {:?} for the run", k));
+        enigo.key_down(k);
+      }
+    }
 
-    for ev in events {
+    while opts.repeat == 0 || pass < opts.repeat {
       if stop.load(Ordering::SeqCst) {
-        println!("playback stopped");
         break;
       }
-      let scheduled = apply_offset(ev.at, offset_ms);
-      wait_until(start, scheduled, &stop);
-
-      match ev.action {
-        KeyAction::Down(k) => {
-          println!(
-            "play: {:?} DOWN at {} ms (offset {} ms)",
-            k,
-            scheduled.as_millis(),
-            offset_ms
-          );
-          enigo.key_down(k);
+      if pass > 0 {
+        let (min, max) = opts.loop_gap_ms;
+        let gap = rng.gen_range(min..=max.max(min));
+        if gap > 0 {
+          logbuf::push_line(&log, format!("loop gap {} ms", gap));
+          sleep_unless_stopped(Duration::from_millis(gap), &stop);
         }
-        KeyAction::Up(k) => {
-          println!(
-            "play: {:?} UP at {} ms (offset {} ms)",
-            k,
-            scheduled.as_millis(),
-            offset_ms
-          );
-          enigo.key_up(k);
+        if stop.load(Ordering::SeqCst) {
+          break;
         }
       }
+      pass += 1;
+      logbuf::push_line(&log, format!("playback pass {}", pass));
+      play_pass(
+        &events,
+        &stop,
+        &paused,
+        &opts,
+        &mut rng,
+        &mut enigo,
+        &mut held,
+        &mut errors_ms,
+        &mut sends_ms,
+        &log,
+      );
     }
+    // Whether stopped or finished, never leave anything logically held in the target app.
+    held.release_all(&mut enigo, &log, opts.dry_run);
+    if !opts.dry_run {
+      for &k in opts.wrap_modifiers.iter().rev() {
+        enigo.key_up(k);
+      }
+    }
+    *report.lock().unwrap() =
+      PlaybackReport::from_errors(&errors_ms, &sends_ms, startup, opts.seed);
   })
 }
 
+/// Keys and buttons with an outstanding Down during playback.
+#[derive(Default)]
+struct Held {
+  keys: HashSet<Key>,
+  buttons: HashSet<MouseButton>,
+}
+
+impl Held {
+  fn release_all(&mut self, enigo: &mut Enigo, log: &LogBuffer, dry_run: bool) {
+    let prefix = if dry_run { "dry run" } else { "play" };
+    for k in self.keys.drain() {
+      logbuf::push_line(log, format!("{}: releasing held {:?}", prefix, k));
+      if !dry_run {
+        enigo.key_up(k);
+      }
+    }
+    for b in self.buttons.drain() {
+      logbuf::push_line(log, format!("{}: releasing held {:?}", prefix, b));
+      if !dry_run {
+        enigo.mouse_up(b);
+      }
+    }
+  }
+}
+
+/// Run one pass over the timeline, scheduling relative to the moment the pass starts.
+/// The absolute error of each sent event is appended to `errors_ms`, and the time its
+/// send call took to `sends_ms`.
+#[allow(clippy::too_many_arguments)]
+fn play_pass(
+  events: &[TimedEvent],
+  stop: &Arc<AtomicBool>,
+  paused: &Arc<AtomicBool>,
+  opts: &PlaybackOptions,
+  rng: &mut StdRng,
+  enigo: &mut Enigo,
+  held: &mut Held,
+  errors_ms: &mut Vec<f64>,
+  sends_ms: &mut Vec<f64>,
+  log: &LogBuffer,
+) {
+  let start = Instant::now();
+  let offset_ms = opts.offset_ms;
+  let mut previous = Duration::ZERO;
+  let mut paused_for = Duration::ZERO;
+  let min_gap = Duration::from_millis(opts.min_gap_ms);
+  // When the last key or button event actually fired, relative to `start`.
+  let mut last_fired: Option<Duration> = None;
+  let mut gap_shift = Duration::ZERO;
+
+  for ev in events.iter().copied() {
+    if stop.load(Ordering::SeqCst) {
+      logbuf::push_line(log, "playback stopped".into());
+      break;
+    }
+    let mut scheduled = apply_offset(ev.at, offset_ms);
+    if opts.jitter_ms > 0.0 {
+      // Never let jitter reorder events.
+      scheduled = apply_jitter(scheduled, opts.jitter_ms, rng).max(previous);
+    }
+    previous = scheduled;
+    scheduled += gap_shift;
+    let gapped = !matches!(ev.action, KeyAction::MouseMove { .. });
+    if gapped
+      && let Some(fired) = last_fired
+      && scheduled + paused_for < fired + min_gap
+    {
+      let push = fired + min_gap - (scheduled + paused_for);
+      scheduled += push;
+      if opts.gap_mode == GapMode::Shift {
+        gap_shift += push;
+      }
+    }
+    wait_until(start, scheduled + paused_for, opts.timing, stop, paused, &mut paused_for);
+    if stop.load(Ordering::SeqCst) {
+      logbuf::push_line(log, "playback stopped".into());
+      break;
+    }
+    let target = (scheduled + paused_for).as_secs_f64();
+    errors_ms.push((start.elapsed().as_secs_f64() - target).abs() * 1000.0);
+    if gapped {
+      last_fired = Some(start.elapsed());
+    }
+
+    if !matches!(ev.action, KeyAction::MouseMove { .. }) {
+      logbuf::push_line(
+        log,
+        format!(
+          "{}: {:?} at {} ms (offset {} ms)",
+          if opts.dry_run { "dry run" } else { "play" },
+          ev.action,
+          scheduled.as_millis(),
+          offset_ms
+        ),
+      );
+    }
+    // Tracked in a dry run too, so its log shows what a stop would release.
+    match ev.action {
+      KeyAction::Down(k) => {
+        held.keys.insert(k);
+      }
+      KeyAction::Up(k) => {
+        held.keys.remove(&k);
+      }
+      KeyAction::ButtonDown(b) => {
+        held.buttons.insert(b);
+      }
+      KeyAction::ButtonUp(b) => {
+        held.buttons.remove(&b);
+      }
+      KeyAction::MouseMove { .. }
+      | KeyAction::Type(_)
+      | KeyAction::GamepadDown(_)
+      | KeyAction::GamepadUp(_) => {}
+    }
+    if opts.dry_run {
+      continue;
+    }
+    let sending = Instant::now();
+    send_action(enigo, ev.action);
+    sends_ms.push(sending.elapsed().as_secs_f64() * 1000.0);
+  }
+}
+
+/// The process-wide input sender. `Enigo::new` opens an X display connection or
+/// event source, so it is created once and each playback (or step) holds it while
+/// sending. A playback holds it for its whole run, so `type_text`, `release_modifiers`
+/// and stepping block until a running playback ends; callers that must not wait
+/// check `playing` or stop the playback first.
+pub fn shared_enigo() -> MutexGuard<'static, Enigo> {
+  static ENIGO: OnceLock<Mutex<Enigo>> = OnceLock::new();
+  let enigo = ENIGO.get_or_init(|| Mutex::new(Enigo::new()));
+  // A panicking playback leaves nothing inconsistent in Enigo itself.
+  enigo.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Type `text` into the focused window right now.
+pub fn type_text(text: &str) {
+  shared_enigo().key_sequence(text);
+}
+
+/// Send a release for every modifier, whether or not anything pressed it. Clears a
+/// modifier the OS still thinks is down after a press whose release never arrived.
+pub fn release_modifiers() {
+  let mut enigo = shared_enigo();
+  for k in [Key::Shift, Key::Control, Key::Alt, Key::Meta] {
+    enigo.key_up(k);
+  }
+}
+
+/// Send a single action to the OS right now.
+pub fn send_action(enigo: &mut Enigo, action: KeyAction) {
+  match action {
+    KeyAction::Down(k) => enigo.key_down(k),
+    KeyAction::Up(k) => enigo.key_up(k),
+    KeyAction::MouseMove { x, y } => enigo.mouse_move_to(x.round() as i32, y.round() as i32),
+    KeyAction::ButtonDown(b) => enigo.mouse_down(b),
+    KeyAction::ButtonUp(b) => enigo.mouse_up(b),
+    KeyAction::Type(c) => enigo.key_sequence(&c.to_string()),
+    // There is no portable way to emulate a controller; mapped buttons were already
+    // turned into keys by `map_gamepad`.
+    KeyAction::GamepadDown(_) | KeyAction::GamepadUp(_) => {}
+  }
+}
+
 fn apply_offset(at: Duration, offset_ms: i64) -> Duration {
   if offset_ms >= 0 {
     at + Duration::from_millis(offset_ms as u64)
@@ -57,23 +432,120 @@ fn apply_offset(at: Duration, offset_ms: i64) -> Duration {
   }
 }
 
-/// Hybrid sleep+spin to hit the scheduled time more tightly.
-fn wait_until(start: Instant, scheduled: Duration, stop: &Arc<AtomicBool>) {
+/// Shift `at` by a normally distributed amount with standard deviation `std_ms`.
+fn apply_jitter(at: Duration, std_ms: f64, rng: &mut StdRng) -> Duration {
+  // Box-Muller transform.
+  let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+  let u2: f64 = rng.r#gen();
+  let noise_ms = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos() * std_ms;
+  let ms = (at.as_secs_f64() * 1000.0 + noise_ms).max(0.0);
+  Duration::from_secs_f64(ms / 1000.0)
+}
+
+/// Sleep for `duration`, waking every `PAUSE_POLL` to return early once `stop` is set.
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+  let end = Instant::now() + duration;
+  while !stop.load(Ordering::SeqCst) {
+    let now = Instant::now();
+    if now >= end {
+      break;
+    }
+    thread::sleep((end - now).min(PAUSE_POLL));
+  }
+}
+
+/// Shift each key's events by its entry in `offsets`, re-sorting if anything moved.
+fn apply_key_offsets(mut events: Vec<TimedEvent>, offsets: &HashMap<Key, i64>) -> Vec<TimedEvent> {
+  if offsets.values().all(|&ms| ms == 0) {
+    return events;
+  }
+  for ev in &mut events {
+    if let KeyAction::Down(k) | KeyAction::Up(k) = ev.action
+      && let Some(&ms) = offsets.get(&k)
+    {
+      ev.at = apply_offset(ev.at, ms);
+    }
+  }
+  events.sort_by_key(|e| e.at);
+  events
+}
+
+/// Swap each press and release to its `remap` target. Runs after the per-key offsets,
+/// which stay keyed by the recorded key.
+pub fn remap_keys(mut events: Vec<TimedEvent>, remap: &HashMap<Key, Key>) -> Vec<TimedEvent> {
+  for ev in &mut events {
+    if let KeyAction::Down(k) | KeyAction::Up(k) = &mut ev.action
+      && let Some(&target) = remap.get(k)
+    {
+      *k = target;
+    }
+  }
+  events
+}
+
+/// Turn controller button events into presses and releases of their `mapping` key.
+/// Unmapped buttons stay as they are and are not sent.
+pub fn map_gamepad(
+  mut events: Vec<TimedEvent>,
+  mapping: &HashMap<GamepadButton, Key>,
+) -> Vec<TimedEvent> {
+  for ev in &mut events {
+    ev.action = match ev.action {
+      KeyAction::GamepadDown(b) => mapping.get(&b).map_or(ev.action, |&k| KeyAction::Down(k)),
+      KeyAction::GamepadUp(b) => mapping.get(&b).map_or(ev.action, |&k| KeyAction::Up(k)),
+      other => other,
+    };
+  }
+  events
+}
+
+/// Remove the presses and releases of every key matching `drop`.
+pub fn drop_keys(events: Vec<TimedEvent>, drop: impl Fn(&Key) -> bool) -> Vec<TimedEvent> {
+  events
+    .into_iter()
+    .filter(|e| match &e.action {
+      KeyAction::Down(k) | KeyAction::Up(k) => !drop(k),
+      _ => true,
+    })
+    .collect()
+}
+
+/// Hybrid sleep+spin to hit the scheduled time more tightly. Time spent paused is
+/// added to `paused_for`, and to the deadline, so later events keep their spacing.
+fn wait_until(
+  start: Instant,
+  mut scheduled: Duration,
+  mode: TimingMode,
+  stop: &Arc<AtomicBool>,
+  paused: &Arc<AtomicBool>,
+  paused_for: &mut Duration,
+) {
   loop {
-    let elapsed = Instant::now().duration_since(start);
     if stop.load(Ordering::SeqCst) {
       break;
     }
+    if paused.load(Ordering::SeqCst) {
+      let pause_start = Instant::now();
+      while paused.load(Ordering::SeqCst) && !stop.load(Ordering::SeqCst) {
+        thread::sleep(PAUSE_POLL);
+      }
+      let pause = pause_start.elapsed();
+      *paused_for += pause;
+      scheduled += pause;
+      continue;
+    }
+    let elapsed = Instant::now().duration_since(start);
     if elapsed >= scheduled {
       break;
     }
     let remaining = scheduled - elapsed;
     // Sleep for coarse remaining minus a small guard, then spin for the rest.
-    if remaining > Duration::from_micros(500) {
-      let sleep_dur = remaining - Duration::from_micros(200);
+    if remaining > mode.spin_threshold() {
+      // Wake at least every PAUSE_POLL so a pause takes effect promptly.
+      let sleep_dur = remaining.saturating_sub(mode.sleep_guard()).min(PAUSE_POLL);
       thread::sleep(sleep_dur);
     } else {
-      // Spin for sub-500us windows to reduce jitter.
+      // Spin for short windows to reduce jitter.
       std::hint::spin_loop();
     }
   }
@@ -1,15 +1,63 @@
 use eframe::egui::{self, Color32};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-mod macro_play;
-mod schema;
-mod state;
-use state::{key_label, AppState};
+mod cli;
+mod timeline_view;
+use auto_note::chords;
+use auto_note::input_device::{self, InputDevice};
+use auto_note::macro_play::{GapMode, TimingMode};
+use auto_note::persist;
+use auto_note::schema::{GamepadButton, KeyAction};
+use auto_note::settings::Settings;
+use auto_note::state::{self, action_label, key_label, AppState, Hotkey, MergeStrategy};
+use auto_note::stats::{self, OutlierMethod};
+use auto_note::text_timeline;
+use auto_note::transform::TieOrder;
+use auto_note::validate;
+
+/// How long "Clear All" and "Shift All" wait for their confirming second click.
+const CONFIRM_WINDOW: Duration = Duration::from_secs(3);
+/// How often changed settings are written back to the settings file.
+const SETTINGS_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+/// Time to focus the target window after pressing the test keystroke "Send".
+const TEST_SEND_DELAY: Duration = Duration::from_secs(3);
+/// Size in pixels of timeline images written by the "PNG" button.
+const PNG_EXPORT_SIZE: (u32, u32) = (1200, 300);
+/// Timing differences beyond this many ms are shown in red in the diff view.
+const DIFF_HIGHLIGHT_MS: f64 = 20.0;
+
+/// Start the command server if `AUTO_NOTE_COMMAND_PORT` names a port.
+#[cfg(feature = "command-server")]
+fn start_command_server(state: &AppState) {
+  use auto_note::command_server;
+  let Ok(port) = std::env::var(command_server::PORT_ENV) else {
+    return;
+  };
+  let result = port
+    .trim()
+    .parse::<u16>()
+    .map_err(|e| e.to_string())
+    .and_then(|port| command_server::spawn(state.clone(), port).map_err(|e| e.to_string()));
+  if let Err(e) = result {
+    state.log(format!("Command server not started on `{}`: {}", port, e));
+  }
+}
 
 fn main() -> eframe::Result<()> {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if !args.is_empty() {
+    std::process::exit(cli::run(&args));
+  }
+
   let state = AppState::new();
+  state.load_settings();
   state.spawn_global_listener();
+  #[cfg(feature = "gamepad")]
+  auto_note::gamepad::spawn_listener(state.clone());
+  #[cfg(feature = "command-server")]
+  start_command_server(&state);
 
   let options = eframe::NativeOptions {
     viewport: egui::ViewportBuilder::default()
@@ -26,6 +74,50 @@ fn main() -> eframe::Result<()> {
         state: state.clone(),
         overlay_open: true,
         key_input: String::new(),
+        samples_path: "samples.json".into(),
+        library_dir: "library".into(),
+        file_status: String::new(),
+        quantize_bpm: 120.0,
+        quantize_division: 4,
+        smooth_keys: "w a s d".into(),
+        smooth_factor: 0.5,
+        merge_strategy: MergeStrategy::Overlay,
+        timeline_zoom: 100.0,
+        scrub_ms: 0,
+        midi_bpm: 120.0,
+        last_autosave: Instant::now(),
+        saved_settings: state.settings(),
+        last_settings_check: Instant::now(),
+        sample_search: String::new(),
+        tag_input: String::new(),
+        layout_path: "layout.json".into(),
+        schedule_interval_s: 60.0,
+        concat_gap_ms: 0,
+        hold_target_ms: 50,
+        scale_factor: 1.0,
+        stats_raw: false,
+        scale_anchor_ms: 0,
+        text_timeline: String::new(),
+        hold_key: "space".into(),
+        event_edit: None,
+        gamepad_button: GamepadButton::South,
+        gamepad_key: String::new(),
+        remap_from: String::new(),
+        remap_to: String::new(),
+        clear_armed_at: None,
+        shift_all_ms: 0,
+        shift_all_armed_at: None,
+        align_reference: None,
+        start_at_input: "+10".into(),
+        countdown_total: None,
+        test_text: "auto-note test".into(),
+        hold_start_ms: 0,
+        hold_duration_ms: 1000,
+        overlay_style: OverlayStyle::default(),
+        chord_window_ms: 15,
+        show_event_at: true,
+        input_devices: input_device::list(),
+        show_event_delta: false,
       })
     }),
   )
@@ -35,14 +127,194 @@ struct RecorderApp {
   state: AppState,
   overlay_open: bool,
   key_input: String,
+  samples_path: String,
+  /// Folder holding one JSON file per sample.
+  library_dir: String,
+  file_status: String,
+  quantize_bpm: f64,
+  quantize_division: u32,
+  /// Movement smoothing: key names separated by spaces, and the 0-1 smoothing factor.
+  smooth_keys: String,
+  smooth_factor: f64,
+  merge_strategy: MergeStrategy,
+  /// Piano-roll scale in pixels per second.
+  timeline_zoom: f32,
+  /// Where "Play From Here" starts, in ms into the selected sample.
+  scrub_ms: u64,
+  /// Tempo written into exported MIDI files.
+  midi_bpm: f64,
+  last_autosave: Instant,
+  /// Settings as last loaded or saved, to spot changes worth writing.
+  saved_settings: Settings,
+  last_settings_check: Instant,
+  sample_search: String,
+  tag_input: String,
+  layout_path: String,
+  schedule_interval_s: f64,
+  /// Pause between the two halves when one sample is dropped onto another.
+  concat_gap_ms: u64,
+  hold_target_ms: u64,
+  /// Time scaling for the selected sample: factor and the ms it scales around.
+  scale_factor: f64,
+  scale_anchor_ms: u64,
+  /// Include every slot's values in the stats export.
+  stats_raw: bool,
+  /// Draft for the `key@ms` timeline editor.
+  text_timeline: String,
+  /// "Add Hold" form: key name, start and duration in ms.
+  hold_key: String,
+  /// Event time being dragged in the event list, as (sample, event, ms); it is applied
+  /// once the drag ends so a drag is a single undo step.
+  event_edit: Option<(usize, usize, u64)>,
+  /// "Gamepad Keys" form: controller button and the key it plays back as.
+  gamepad_button: GamepadButton,
+  gamepad_key: String,
+  /// "Key Remap" form: recorded key and the key to send instead.
+  remap_from: String,
+  remap_to: String,
+  hold_start_ms: u64,
+  hold_duration_ms: u64,
+  /// When "Clear All" was first clicked; a second click within `CONFIRM_WINDOW`
+  /// clears.
+  clear_armed_at: Option<Instant>,
+  /// "Shift All" amount in ms, and when it was first clicked.
+  shift_all_ms: i64,
+  shift_all_armed_at: Option<Instant>,
+  /// Sample the selected one is aligned against.
+  align_reference: Option<usize>,
+  /// When to start a timed playback: `+N` seconds or `HH:MM[:SS]` UTC.
+  start_at_input: String,
+  /// Pending timed start and the wait left when it was first shown, for its progress
+  /// bar.
+  countdown_total: Option<(SystemTime, Duration)>,
+  /// Text the "Send" button types to check that input reaches other windows.
+  test_text: String,
+  overlay_style: OverlayStyle,
+  /// Downs closer together than this are listed as one chord.
+  chord_window_ms: u64,
+  /// Event list columns: absolute `at` and the gap since the previous event.
+  show_event_at: bool,
+  show_event_delta: bool,
+  /// Keyboards offered for "Record keys from", as of startup or the last refresh.
+  input_devices: Vec<InputDevice>,
+}
+
+/// Look of the key boxes in the overlay window.
+struct OverlayStyle {
+  key_size: egui::Vec2,
+  pressed: Color32,
+  released: Color32,
+  text: Color32,
+}
+
+impl Default for OverlayStyle {
+  fn default() -> Self {
+    Self {
+      key_size: egui::vec2(60.0, 34.0),
+      pressed: Color32::from_rgb(120, 220, 120),
+      released: Color32::from_rgb(60, 60, 60),
+      text: Color32::BLACK,
+    }
+  }
+}
+
+/// Drag-and-drop payload: index of the sample being dragged.
+struct SampleDrag(usize);
+
+impl RecorderApp {
+  fn autosave(&mut self) {
+    self.last_autosave = Instant::now();
+    if let Err(e) = self.state.autosave() {
+      self.state.log(format!("Autosave failed: {}", e));
+    }
+  }
+
+  /// Write the settings file if anything in it changed since the last check. A failed
+  /// write is logged once and retried on the next change.
+  fn save_settings_if_changed(&mut self) {
+    self.last_settings_check = Instant::now();
+    let current = self.state.settings();
+    if current == self.saved_settings {
+      return;
+    }
+    if let Err(e) = self.state.save_settings() {
+      self.state.log(format!("Saving settings failed: {}", e));
+    }
+    self.saved_settings = current;
+  }
+
+  /// Save every sample to `path`, which becomes the samples file.
+  fn save_samples_to(&mut self, path: PathBuf) {
+    self.samples_path = path.display().to_string();
+    self.file_status = match self.state.save_samples(&path) {
+      Ok(0) => format!("Saved to {}", self.samples_path),
+      Ok(n) => format!(
+        "Saved to {} ({} events with unsupported keys skipped)",
+        self.samples_path, n
+      ),
+      Err(e) => format!("Save failed: {}", e),
+    };
+  }
+
+  /// Replace the samples with the ones in `path`, which becomes the samples file.
+  fn open_samples_from(&mut self, path: PathBuf) {
+    self.samples_path = path.display().to_string();
+    self.file_status = match self.state.load_samples(&path) {
+      Ok(0) => format!("Loaded {}", self.samples_path),
+      Ok(n) => format!(
+        "Loaded {} ({} events with unknown keys skipped)",
+        self.samples_path, n
+      ),
+      Err(e @ persist::LoadError::NewerVersion(_)) => e.to_string(),
+      Err(e) => format!("Open failed: {}", e),
+    };
+  }
+}
+
+/// A file dialog for sample files that starts at `current`.
+fn samples_dialog(current: &str) -> rfd::FileDialog {
+  let current = Path::new(current);
+  let mut dialog =
+    rfd::FileDialog::new().add_filter("Samples", &["json", persist::BINARY_EXTENSION]);
+  if let Some(dir) = current.parent().filter(|dir| dir.is_dir()) {
+    dialog = dialog.set_directory(dir);
+  }
+  if let Some(name) = current.file_name().and_then(|name| name.to_str()) {
+    dialog = dialog.set_file_name(name);
+  }
+  dialog
 }
 
 impl eframe::App for RecorderApp {
+  fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+    self.autosave();
+    self.save_settings_if_changed();
+  }
+
   fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
     // Keep UI refreshing so counters update even without mouse movement.
     ctx.request_repaint_after(Duration::from_millis(16));
+    self.state.reap_playback();
+    self.state.stop_if_idle();
+    if self.last_autosave.elapsed() >= *self.state.autosave_interval.lock().unwrap() {
+      self.autosave();
+    }
+    if self.last_settings_check.elapsed() >= SETTINGS_SAVE_INTERVAL {
+      self.save_settings_if_changed();
+    }
+    if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::Z)) {
+      self.state.undo();
+    }
     egui::CentralPanel::default().show(ctx, |ui| {
       ui.heading("Auto Note Recorder");
+      if let Some(error) = self.state.listener_error.lock().unwrap().as_deref() {
+        egui::Frame::none()
+          .fill(Color32::from_rgb(140, 20, 20))
+          .inner_margin(6.0)
+          .show(ui, |ui| {
+            ui.colored_label(Color32::WHITE, error);
+          });
+      }
       ui.separator();
 
       let is_rec = self.state.recording.load(Ordering::SeqCst);
@@ -50,6 +322,18 @@ impl eframe::App for RecorderApp {
         if ui.add_enabled(!is_rec, egui::Button::new("Start Recording")).clicked() {
           self.state.start_recording();
         }
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if let Some(idx) = selected
+          && ui
+            .add_enabled(
+              !is_rec,
+              egui::Button::new(format!("Continue recording into #{}", idx + 1)),
+            )
+            .on_hover_text("Append a new take after the selected sample's last event")
+            .clicked()
+        {
+          self.state.continue_recording(idx);
+        }
         if ui.add_enabled(is_rec, egui::Button::new("Stop Recording")).clicked() {
           self.state.stop_recording();
         }
@@ -57,9 +341,27 @@ impl eframe::App for RecorderApp {
           self.state.stop_recording();
           self.state.playback_latest();
         }
+        let paused = self.state.playback_paused.load(Ordering::SeqCst);
+        let is_playing = self.state.playing.load(Ordering::SeqCst);
+        let pause_label = if paused { "Resume" } else { "Pause" };
+        if ui.add_enabled(is_playing, egui::Button::new(pause_label)).clicked() {
+          if paused {
+            self.state.resume_playback();
+          } else {
+            self.state.pause_playback();
+          }
+        }
         if ui.button("Stop Playback").clicked() {
           self.state.stop_playback();
         }
+        if ui
+          .button("Release Stuck Keys")
+          .on_hover_text("Send a release for Shift, Ctrl, Alt and Meta")
+          .clicked()
+        {
+          self.state.release_all_modifiers();
+          self.state.log("Released modifier keys".into());
+        }
       });
 
       ui.separator();
@@ -69,13 +371,254 @@ impl eframe::App for RecorderApp {
         if ui.add(egui::DragValue::new(&mut offset_ms).speed(1)).changed() {
           *self.state.playback_offset_ms.lock().unwrap() = offset_ms;
         }
+        let mut clamp = self.state.clamp_offset.load(Ordering::SeqCst);
+        if ui
+          .checkbox(&mut clamp, "Clamp to first event")
+          .on_hover_text("Shift at most to t=0 instead of piling early events up at t=0")
+          .changed()
+        {
+          self.state.clamp_offset.store(clamp, Ordering::SeqCst);
+        }
+        ui.label("Bake into samples:");
+        ui.add(egui::DragValue::new(&mut self.shift_all_ms).speed(1).suffix(" ms"));
+        let armed = self
+          .shift_all_armed_at
+          .is_some_and(|at| at.elapsed() < CONFIRM_WINDOW);
+        if ui
+          .button(if armed { "Confirm?" } else { "Shift All" })
+          .on_hover_text("Move every event in every sample by this much, permanently")
+          .clicked()
+        {
+          if armed {
+            self.state.apply_offset_to_all(self.shift_all_ms);
+            self.shift_all_armed_at = None;
+          } else {
+            self.shift_all_armed_at = Some(Instant::now());
+          }
+        }
+        ui.label("Repeat (0 = loop):");
+        let mut repeat = *self.state.playback_repeat.lock().unwrap();
+        if ui.add(egui::DragValue::new(&mut repeat).speed(1)).changed() {
+          *self.state.playback_repeat.lock().unwrap() = repeat;
+        }
+        ui.label("Loop gap:");
+        let mut gap = self.state.loop_gap_ms.lock().unwrap();
+        let (min, max) = &mut *gap;
+        ui.add(egui::DragValue::new(min).suffix(" ms"))
+          .on_hover_text("Random pause between repeats, at least this long");
+        ui.label("to");
+        ui.add(egui::DragValue::new(max).suffix(" ms"))
+          .on_hover_text("Random pause between repeats, at most this long");
+        if *max < *min {
+          *max = *min;
+        }
+        drop(gap);
+        ui.label("Jitter σ (ms):");
+        let mut jitter_ms = *self.state.jitter_ms.lock().unwrap();
+        if ui
+          .add(egui::DragValue::new(&mut jitter_ms).speed(0.1).clamp_range(0.0..=100.0))
+          .changed()
+        {
+          *self.state.jitter_ms.lock().unwrap() = jitter_ms;
+        }
+        ui.label("Timing:");
+        let mut mode = *self.state.timing_mode.lock().unwrap();
+        egui::ComboBox::from_id_source("timing-mode")
+          .selected_text(format!("{:?}", mode))
+          .show_ui(ui, |ui| {
+            ui.selectable_value(&mut mode, TimingMode::Precise, "Precise");
+            ui.selectable_value(&mut mode, TimingMode::Balanced, "Balanced");
+            ui.selectable_value(&mut mode, TimingMode::PowerSaver, "PowerSaver");
+          });
+        *self.state.timing_mode.lock().unwrap() = mode;
+        let mut dry_run = self.state.dry_run.load(Ordering::SeqCst);
+        if ui
+          .checkbox(&mut dry_run, "Dry run")
+          .on_hover_text("Schedule and log events without sending any input")
+          .changed()
+        {
+          self.state.dry_run.store(dry_run, Ordering::SeqCst);
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Min gap:");
+        let mut min_gap_ms = *self.state.min_gap_ms.lock().unwrap();
+        if ui
+          .add(egui::DragValue::new(&mut min_gap_ms).clamp_range(0..=100).suffix(" ms"))
+          .on_hover_text("Delay key events that would fire closer together than this")
+          .changed()
+        {
+          *self.state.min_gap_ms.lock().unwrap() = min_gap_ms;
+        }
+        let mut gap_mode = self.state.gap_mode.lock().unwrap();
+        ui.radio_value(&mut *gap_mode, GapMode::Shift, "Shift later events")
+          .on_hover_text("Keep the spacing of everything after a delayed event");
+        ui.radio_value(&mut *gap_mode, GapMode::Clamp, "Delay only that event");
+      });
+      ui.horizontal(|ui| {
+        ui.label("Same-time events:");
+        let mut tie_order = self.state.tie_order.lock().unwrap();
+        ui.radio_value(&mut *tie_order, TieOrder::UpsFirst, "Releases first")
+          .on_hover_text("Let keys go before pressing others at the same moment");
+        ui.radio_value(&mut *tie_order, TieOrder::DownsFirst, "Presses first")
+          .on_hover_text("Press new keys before releasing others at the same moment");
+      });
+      ui.horizontal(|ui| {
+        ui.label("Hold while playing:");
+        let mut wrap = self.state.wrap_modifiers.lock().unwrap();
+        for (key, label) in [
+          (enigo::Key::Shift, "Shift"),
+          (enigo::Key::Control, "Ctrl"),
+          (enigo::Key::Alt, "Alt"),
+          (enigo::Key::Meta, "Meta"),
+        ] {
+          let mut on = wrap.contains(&key);
+          if ui.checkbox(&mut on, label).changed() {
+            if on {
+              wrap.push(key);
+            } else {
+              wrap.retain(|k| *k != key);
+            }
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Start at:");
+        ui.add(egui::TextEdit::singleline(&mut self.start_at_input).desired_width(70.0))
+          .on_hover_text("`+N` seconds from now, or `HH:MM:SS` UTC");
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if ui
+          .add_enabled(selected.is_some(), egui::Button::new("Play Selected At"))
+          .on_hover_text("Start on the wall clock, e.g. on several PCs at once")
+          .clicked()
+          && let Some(idx) = selected
+        {
+          match parse_start_time(&self.start_at_input) {
+            Some(when) => self.state.play_at(idx, when),
+            None => self.file_status = format!("Bad start time `{}`", self.start_at_input),
+          }
+        }
+      });
+      ui.horizontal_wrapped(|ui| {
+        ui.label("Hotkeys:");
+        let pending = *self.state.rebinding.lock().unwrap();
+        for (which, name) in [
+          (Hotkey::Record, "Record"),
+          (Hotkey::Playback, "Playback"),
+          (Hotkey::Panic, "Panic"),
+          (Hotkey::PrevSample, "Prev sample"),
+          (Hotkey::NextSample, "Next sample"),
+        ] {
+          ui.label(format!("{}:", name));
+          let text = if pending == Some(which) {
+            "Press a key...".to_string()
+          } else {
+            format!("{:?}", self.state.hotkey(which))
+          };
+          let hint = if which == Hotkey::Panic {
+            "Click, then press the new key. Pressing this key stops recording and playback \
+             at once, so it can't be recorded; rebind it to record Esc."
+          } else {
+            "Click, then press the new key"
+          };
+          if ui.button(text).on_hover_text(hint).clicked() {
+            self.state.begin_rebind(which);
+          }
+        }
       });
       ui.separator();
       let ev_len = self.state.current_events.lock().unwrap().len();
-      ui.label(format!("Recording: {}", if is_rec { "ON" } else { "OFF" }));
-      let is_playing = self.state.playing.load(Ordering::SeqCst);
-      ui.label(format!("Playing: {}", if is_playing { "ON" } else { "OFF" }));
+      let rec_start = *self.state.start.lock().unwrap();
+      match rec_start.filter(|_| is_rec) {
+        Some(started) => {
+          ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("●").color(Color32::RED).size(22.0));
+            ui.label(
+              egui::RichText::new(format!("REC {}", format_elapsed(started.elapsed())))
+                .monospace()
+                .size(18.0)
+                .strong(),
+            );
+            ui.label(format!("{} events", ev_len));
+          });
+        }
+        None => {
+          ui.label("Recording: OFF");
+        }
+      }
+      let unmapped = self.state.unmapped_count.load(Ordering::SeqCst);
+      if unmapped > 0 {
+        ui.colored_label(
+          Color32::from_rgb(230, 160, 40),
+          format!("{} keys not captured (unmapped)", unmapped),
+        )
+        .on_hover_text("These keys aren't in the keyboard layout; load a layout that names them");
+      }
+      let pending = *self.state.timed_start.lock().unwrap();
+      if let Some((idx, when)) = pending {
+        let left = when.duration_since(SystemTime::now()).unwrap_or_default();
+        if self.countdown_total.is_none_or(|(at, _)| at != when) {
+          self.countdown_total = Some((when, left));
+        }
+        let total = self.countdown_total.map_or(left, |(_, total)| total);
+        ui.horizontal(|ui| {
+          let done = 1.0 - left.as_secs_f32() / total.as_secs_f32().max(f32::EPSILON);
+          let text = format!("#{} starts in {:.1} s", idx + 1, left.as_secs_f64());
+          ui.add(egui::ProgressBar::new(done).desired_width(240.0).text(text));
+          if ui.button("Cancel").clicked() {
+            self.state.cancel_play_at();
+          }
+        });
+      } else {
+        self.countdown_total = None;
+      }
+      match self.state.playback_progress() {
+        Some((elapsed, pass)) => {
+          ui.horizontal(|ui| {
+            let done = elapsed.as_secs_f32() / pass.as_secs_f32().max(f32::EPSILON);
+            let text = format!("{} / {}", format_elapsed(elapsed), format_elapsed(pass));
+            ui.add(egui::ProgressBar::new(done).desired_width(240.0).text(text));
+            if ui
+              .button("Cancel")
+              .on_hover_text("Stop playback and release held keys")
+              .clicked()
+            {
+              self.state.stop_playback();
+            }
+          });
+        }
+        None => {
+          ui.label("Playing: OFF");
+        }
+      }
+      if let Some(idx) = self.state.active_index() {
+        let name = self.state.samples.lock().unwrap()[idx].name.clone();
+        ui.label(format!("Active sample: #{} {}", idx + 1, name))
+          .on_hover_text(format!(
+            "{:?} plays it; {:?} / {:?} cycle",
+            self.state.hotkey(Hotkey::Playback),
+            self.state.hotkey(Hotkey::PrevSample),
+            self.state.hotkey(Hotkey::NextSample)
+          ));
+      }
       ui.label(format!("Events captured (current): {}", ev_len));
+      if let Some(report) = *self.state.playback_report.lock().unwrap() {
+        ui.horizontal(|ui| {
+          ui.label(report.to_string()).on_hover_text(format!(
+            "{} events, absolute send-time error",
+            report.events
+          ));
+          let selected = *self.state.selected_sample.lock().unwrap();
+          if ui
+            .add_enabled(selected.is_some(), egui::Button::new("Replay Seed"))
+            .on_hover_text("Play the selected sample again with this run's jitter")
+            .clicked()
+            && let Some(idx) = selected
+          {
+            self.state.replay_with_seed(idx, report.seed);
+          }
+        });
+      }
 
       ui.separator();
       ui.heading("Tracked Keys");
@@ -87,6 +630,129 @@ impl eframe::App for RecorderApp {
           self.key_input.clear();
         }
       });
+      ui.horizontal(|ui| {
+        ui.label("Layout:");
+        ui.add(egui::TextEdit::singleline(&mut self.layout_path).desired_width(140.0));
+        if ui
+          .button("Load layout")
+          .on_hover_text("JSON map of rdev key names to keys, over the US layout")
+          .clicked()
+        {
+          self.file_status = match self.state.load_key_map(Path::new(&self.layout_path)) {
+            Ok(()) => format!("Loaded layout {}", self.layout_path),
+            Err(e) => format!("Layout failed: {}", e),
+          };
+        }
+      });
+      let mut restrict = self.state.restricts_to_tracked();
+      if ui
+        .checkbox(&mut restrict, "Restrict recording to tracked keys")
+        .changed()
+      {
+        self.state.set_restrict_to_tracked(restrict);
+      }
+      ui.horizontal(|ui| {
+        ui.label("Record keys from:");
+        let current = self.state.input_device();
+        let mut choice = current.clone();
+        let selected_text = match &current {
+          None => "All keyboards".to_string(),
+          Some(path) => self
+            .input_devices
+            .iter()
+            .find(|d| &d.path == path)
+            .map_or_else(|| path.display().to_string(), |d| d.name.clone()),
+        };
+        let combo = egui::ComboBox::from_id_source("input-device")
+          .selected_text(selected_text)
+          .show_ui(ui, |ui| {
+            ui.selectable_value(&mut choice, None, "All keyboards");
+            for device in &self.input_devices {
+              let label = format!("{} ({})", device.name, device.path.display());
+              ui.selectable_value(&mut choice, Some(device.path.clone()), label);
+            }
+          });
+        if choice != current {
+          self.state.set_input_device(choice);
+        }
+        if !input_device::SUPPORTED {
+          combo
+            .response
+            .on_hover_text("Picking one keyboard needs Linux and the device-filter feature");
+        } else if ui.small_button("Refresh").clicked() {
+          self.input_devices = input_device::list();
+        }
+      });
+      ui.horizontal(|ui| {
+        let mut auto_stop = self.state.auto_stop_idle_ms.lock().unwrap();
+        let mut enabled = auto_stop.is_some();
+        if ui
+          .checkbox(&mut enabled, "Stop recording after idle")
+          .on_hover_text("Stop automatically once no key or button was pressed for this long")
+          .changed()
+        {
+          *auto_stop = enabled.then_some(3000);
+        }
+        if let Some(idle_ms) = auto_stop.as_mut() {
+          ui.add(
+            egui::DragValue::new(idle_ms)
+              .clamp_range(100..=60_000)
+              .suffix(" ms"),
+          );
+        }
+      });
+      let mut fold_typing = self.state.fold_typing.load(Ordering::SeqCst);
+      if ui
+        .checkbox(&mut fold_typing, "Record Shift+key as typed text")
+        .on_hover_text("Plays back e.g. `H` as text instead of Shift then h")
+        .changed()
+      {
+        self.state.fold_typing.store(fold_typing, Ordering::SeqCst);
+      }
+      ui.horizontal(|ui| {
+        let mut ignore_repeat = self.state.ignore_autorepeat.load(Ordering::SeqCst);
+        if ui
+          .checkbox(&mut ignore_repeat, "Ignore key auto-repeat")
+          .changed()
+        {
+          self.state.ignore_autorepeat.store(ignore_repeat, Ordering::SeqCst);
+        }
+        ui.add_enabled_ui(ignore_repeat, |ui| {
+          let mut window = self.state.autorepeat_window_ms.lock().unwrap();
+          let mut while_held = *window == state::AUTOREPEAT_WHILE_HELD;
+          if ui
+            .checkbox(&mut while_held, "while held")
+            .on_hover_text("Off: only drop repeats within the window, keeping fast taps")
+            .changed()
+          {
+            *window = if while_held {
+              state::AUTOREPEAT_WHILE_HELD
+            } else {
+              100
+            };
+          }
+          if !while_held {
+            ui.add(
+              egui::DragValue::new(&mut *window)
+                .clamp_range(1..=2000)
+                .suffix(" ms"),
+            );
+          }
+        });
+      });
+      ui.horizontal(|ui| {
+        ui.label("Debounce:");
+        let mut debounce_ms = self.state.debounce_ms.lock().unwrap();
+        ui.add(
+          egui::DragValue::new(&mut *debounce_ms)
+            .clamp_range(0..=50)
+            .suffix(" ms"),
+        )
+        .on_hover_text(
+          "Drop a key's release and re-press (or press and release) this close together, \
+           for keyboards that double-fire. Also drops real taps that fast; 0 = off",
+        );
+      });
       ui.horizontal_wrapped(|ui| {
         ui.label("Registered:");
         let keys = self.state.tracked_keys();
@@ -99,57 +765,1126 @@ impl eframe::App for RecorderApp {
           });
         }
       });
+      let keys = self.state.tracked_keys();
+      if !keys.is_empty() {
+        egui::CollapsingHeader::new("Per-key Offsets").show(ui, |ui| {
+          egui::Grid::new("key-offsets").show(ui, |ui| {
+            let mut offsets = self.state.per_key_offset.lock().unwrap();
+            for key in keys {
+              ui.label(key_label(&key));
+              let mut ms = offsets.get(&key).copied().unwrap_or(0);
+              if ui
+                .add(egui::DragValue::new(&mut ms).speed(1).suffix(" ms"))
+                .changed()
+              {
+                offsets.insert(key, ms);
+              }
+              ui.end_row();
+            }
+          });
+        });
+      }
       ui.horizontal_wrapped(|ui| {
         let label = if self.overlay_open { "Hide Overlay" } else { "Show Overlay" };
         if ui.button(label).clicked() {
           self.overlay_open = !self.overlay_open;
         }
+        let style = &mut self.overlay_style;
+        ui.label("Box:");
+        ui.add(egui::DragValue::new(&mut style.key_size.x).clamp_range(16.0..=200.0));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut style.key_size.y).clamp_range(16.0..=200.0));
+        ui.label("Pressed:");
+        ui.color_edit_button_srgba(&mut style.pressed);
+        ui.label("Released:");
+        ui.color_edit_button_srgba(&mut style.released);
+        ui.label("Text:");
+        ui.color_edit_button_srgba(&mut style.text);
       });
 
       ui.separator();
       ui.heading("Recorded Samples");
-      if ui.button("Merge All Samples").clicked() {
-        self.state.merge_samples();
+      ui.horizontal(|ui| {
+        ui.label("File:");
+        ui.add(egui::TextEdit::singleline(&mut self.samples_path).desired_width(160.0))
+          .on_hover_text("A .bin extension saves in the compact binary format");
+        if ui.button("Save").on_hover_text("Save to the file named here").clicked() {
+          self.save_samples_to(PathBuf::from(&self.samples_path));
+        }
+        if ui.button("Save As...").clicked()
+          && let Some(path) = samples_dialog(&self.samples_path).save_file()
+        {
+          self.save_samples_to(path);
+        }
+        if ui.button("Open...").clicked()
+          && let Some(path) = samples_dialog(&self.samples_path).pick_file()
+        {
+          self.open_samples_from(path);
+        }
+        if ui
+          .add_enabled(self.state.can_undo(), egui::Button::new("Undo"))
+          .on_hover_text("Ctrl+Z")
+          .clicked()
+        {
+          self.state.undo();
+        }
+        let armed = self
+          .clear_armed_at
+          .is_some_and(|at| at.elapsed() < CONFIRM_WINDOW);
+        let clear_label = if armed { "Confirm?" } else { "Clear All" };
+        if ui
+          .button(clear_label)
+          .on_hover_text("Remove every sample; click twice to confirm")
+          .clicked()
+        {
+          if armed {
+            self.state.clear_all_samples();
+            self.clear_armed_at = None;
+          } else {
+            self.clear_armed_at = Some(Instant::now());
+          }
+        }
+        if ui.button("Import CSV").clicked() {
+          self.file_status = match self.state.import_csv(Path::new(&self.samples_path)) {
+            Ok(n) => format!("Imported {} events from {}", n, self.samples_path),
+            Err(e) => format!("Import failed: {}", e),
+          };
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Library:");
+        ui.add(egui::TextEdit::singleline(&mut self.library_dir).desired_width(160.0));
+        if ui.button("Open Library").clicked() {
+          self.file_status = match self.state.load_library(Path::new(&self.library_dir)) {
+            Ok(n) => format!("Loaded {} samples from {}", n, self.library_dir),
+            Err(e) => format!("Open failed: {}", e),
+          };
+        }
+      });
+      let mut micros = self.state.save_micros.load(Ordering::SeqCst);
+      if ui
+        .checkbox(&mut micros, "Save with µs precision")
+        .on_hover_text("Keep sub-millisecond timing; older builds can't read these files")
+        .changed()
+      {
+        self.state.save_micros.store(micros, Ordering::SeqCst);
+      }
+      let mut rebase_on_import = self.state.rebase_on_import.load(Ordering::SeqCst);
+      if ui
+        .checkbox(&mut rebase_on_import, "Start loaded samples at 0 ms")
+        .on_hover_text("Remove the dead time before the first event when opening or importing")
+        .changed()
+      {
+        self.state.rebase_on_import.store(rebase_on_import, Ordering::SeqCst);
+      }
+      if !self.file_status.is_empty() {
+        ui.label(&self.file_status);
+      }
+      ui.horizontal(|ui| {
+        ui.label("Merge:");
+        ui.radio_value(&mut self.merge_strategy, MergeStrategy::Overlay, "Overlay");
+        let sequential = matches!(self.merge_strategy, MergeStrategy::Sequential { .. });
+        if ui.radio(sequential, "Sequential").clicked() && !sequential {
+          self.merge_strategy = MergeStrategy::Sequential { gap_ms: 0 };
+        }
+        if let MergeStrategy::Sequential { gap_ms } = &mut self.merge_strategy {
+          ui.add(egui::DragValue::new(gap_ms).prefix("gap ").suffix(" ms"));
+        }
+        if ui.button("Merge Checked").clicked() {
+          let checked: Vec<usize> = {
+            let samples = self.state.samples.lock().unwrap();
+            (0..samples.len()).filter(|&i| samples[i].checked).collect()
+          };
+          self.state.merge_samples(&checked, self.merge_strategy);
+        }
+        if ui
+          .button("Play Checked Layered")
+          .on_hover_text("Play the checked samples at the same time as separate tracks")
+          .clicked()
+        {
+          let checked: Vec<usize> = {
+            let samples = self.state.samples.lock().unwrap();
+            (0..samples.len()).filter(|&i| samples[i].checked).collect()
+          };
+          self.state.play_tracks(&checked);
+        }
+        ui.label("Drop gap:")
+          .on_hover_text("Drag a row's handle onto another row to append it");
+        ui.add(egui::DragValue::new(&mut self.concat_gap_ms).suffix(" ms"));
+      });
+      ui.horizontal(|ui| {
+        ui.label("Quantize BPM:");
+        ui.add(egui::DragValue::new(&mut self.quantize_bpm).speed(1.0).clamp_range(1.0..=999.0));
+        ui.label("Division:");
+        ui.add(egui::DragValue::new(&mut self.quantize_division).clamp_range(1..=64));
+      });
+      ui.horizontal(|ui| {
+        ui.label("Movement keys:");
+        ui.add(egui::TextEdit::singleline(&mut self.smooth_keys).desired_width(80.0));
+        ui.add(egui::Slider::new(&mut self.smooth_factor, 0.0..=1.0).text("smoothing"));
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if ui
+          .add_enabled(selected.is_some(), egui::Button::new("Smooth Selected"))
+          .on_hover_text("Fade direction changes in and out with short taps")
+          .clicked()
+          && let Some(idx) = selected
+        {
+          let keys: Option<Vec<_>> = self
+            .smooth_keys
+            .split_whitespace()
+            .map(persist::string_to_key_loose)
+            .collect();
+          match keys {
+            Some(keys) => self.state.smooth_movement_sample(idx, &keys, self.smooth_factor),
+            None => self.file_status = format!("Unknown key in `{}`", self.smooth_keys.trim()),
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Hold:");
+        ui.add(egui::Slider::new(&mut self.hold_target_ms, 5..=500).suffix(" ms"));
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if ui
+          .add_enabled(selected.is_some(), egui::Button::new("Normalize Holds"))
+          .on_hover_text("Set every key hold in the selected sample to this length")
+          .clicked()
+          && let Some(idx) = selected
+        {
+          self.state.normalize_holds_sample(idx, self.hold_target_ms);
+        }
+        ui.label("Chord window:");
+        ui.add(
+          egui::DragValue::new(&mut self.chord_window_ms)
+            .clamp_range(0..=200)
+            .suffix(" ms"),
+        );
+        ui.label("Event times:");
+        ui.checkbox(&mut self.show_event_at, "Absolute");
+        ui.checkbox(&mut self.show_event_delta, "Delta")
+          .on_hover_text("Time since the previous event");
+      });
+      ui.horizontal(|ui| {
+        ui.label("Scale:");
+        ui.add(
+          egui::DragValue::new(&mut self.scale_factor)
+            .speed(0.01)
+            .clamp_range(0.1..=10.0)
+            .prefix("×"),
+        );
+        ui.label("around");
+        ui.add(egui::DragValue::new(&mut self.scale_anchor_ms).suffix(" ms"));
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if ui
+          .add_enabled(selected.is_some(), egui::Button::new("Scale Time"))
+          .on_hover_text("Stretch or compress the selected sample around the anchor")
+          .clicked()
+          && let Some(idx) = selected
+        {
+          self.state.time_scale_sample(idx, self.scale_factor, self.scale_anchor_ms);
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Reference:");
+        let names: Vec<String> = self
+          .state
+          .samples
+          .lock()
+          .unwrap()
+          .iter()
+          .enumerate()
+          .map(|(i, s)| format!("#{} {}", i + 1, s.name))
+          .collect();
+        let current = self
+          .align_reference
+          .and_then(|i| names.get(i).cloned())
+          .unwrap_or_else(|| "none".into());
+        egui::ComboBox::from_id_source("align-reference")
+          .selected_text(current)
+          .show_ui(ui, |ui| {
+            for (i, name) in names.iter().enumerate() {
+              ui.selectable_value(&mut self.align_reference, Some(i), name);
+            }
+          });
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if ui
+          .add_enabled(
+            selected.is_some() && self.align_reference.is_some(),
+            egui::Button::new("Align to Reference"),
+          )
+          .on_hover_text("Set the selected sample's offset to best match the reference")
+          .clicked()
+          && let (Some(idx), Some(reference)) = (selected, self.align_reference)
+        {
+          self.file_status = match self.state.align_sample(idx, reference) {
+            Some(a) => format!(
+              "Offset {} ms, residual {:.1} ms over {} events",
+              a.offset_ms, a.residual_ms, a.matched
+            ),
+            None => "Nothing to align: no shared key events".into(),
+          };
+        }
+      });
+      let selected = *self.state.selected_sample.lock().unwrap();
+      if let (Some(idx), Some(reference)) = (selected, self.align_reference) {
+        egui::CollapsingHeader::new("Diff vs Reference").show(ui, |ui| {
+          let entries = {
+            let samples = self.state.samples.lock().unwrap();
+            match (samples.get(reference), samples.get(idx)) {
+              (Some(r), Some(t)) => stats::diff(&r.events, &t.events),
+              _ => Vec::new(),
+            }
+          };
+          if entries.is_empty() {
+            ui.label("No key events to compare.");
+            return;
+          }
+          egui::ScrollArea::vertical()
+            .id_source("diff-scroll")
+            .max_height(200.0)
+            .show(ui, |ui| {
+              egui::Grid::new("diff-grid").striped(true).show(ui, |ui| {
+                for header in ["Key", "Mode", "#", "Reference", "Take", "Delta"] {
+                  ui.strong(header);
+                }
+                ui.end_row();
+                let ms = |at: Option<Duration>| {
+                  at.map_or("-".to_string(), |at| {
+                    format!("{:.1}", at.as_secs_f64() * 1000.0)
+                  })
+                };
+                for entry in &entries {
+                  ui.label(key_label(&entry.key));
+                  ui.label(format!("{:?}", entry.mode));
+                  ui.label(entry.index.to_string());
+                  ui.label(ms(entry.reference));
+                  ui.label(ms(entry.take));
+                  match entry.delta_ms() {
+                    Some(d) if d.abs() > DIFF_HIGHLIGHT_MS => {
+                      ui.colored_label(Color32::RED, format!("{:+.1}", d));
+                    }
+                    Some(d) => {
+                      ui.label(format!("{:+.1}", d));
+                    }
+                    None if entry.take.is_none() => {
+                      ui.colored_label(Color32::RED, "missing");
+                    }
+                    None => {
+                      ui.colored_label(Color32::YELLOW, "extra");
+                    }
+                  }
+                  ui.end_row();
+                }
+              });
+            });
+        });
       }
+      ui.horizontal(|ui| {
+        let step = *self.state.step_cursor.lock().unwrap();
+        ui.label(format!("Step cursor: {}", step));
+        if ui.button("Step").clicked() {
+          self.state.play_next_step();
+        }
+        if ui.button("Reset Step").clicked() {
+          self.state.reset_step();
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Test keystrokes:");
+        ui.add(egui::TextEdit::singleline(&mut self.test_text).desired_width(120.0));
+        let pending = *self.state.test_send_at.lock().unwrap();
+        match pending {
+          Some(at) => {
+            let left = at.saturating_duration_since(Instant::now());
+            ui.label(format!("Typing in {:.1} s", left.as_secs_f64()));
+          }
+          None => {
+            if ui
+              .button("Send")
+              .on_hover_text("Type the text into the focused window after a short countdown")
+              .clicked()
+            {
+              self.state.send_test_text(self.test_text.clone(), TEST_SEND_DELAY);
+            }
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Search:");
+        ui.add(
+          egui::TextEdit::singleline(&mut self.sample_search)
+            .hint_text("name or tag")
+            .desired_width(140.0),
+        );
+        ui.label("Tag:");
+        ui.add(egui::TextEdit::singleline(&mut self.tag_input).desired_width(80.0));
+        let selected = *self.state.selected_sample.lock().unwrap();
+        if ui
+          .add_enabled(selected.is_some(), egui::Button::new("Tag Selected"))
+          .clicked()
+          && let Some(idx) = selected
+        {
+          self.state.add_tag(idx, &self.tag_input);
+          self.tag_input.clear();
+        }
+      });
+      ui.horizontal(|ui| {
+        ui.label("Schedule every:");
+        ui.add(
+          egui::DragValue::new(&mut self.schedule_interval_s)
+            .speed(1.0)
+            .clamp_range(1.0..=86_400.0)
+            .suffix(" s"),
+        );
+        let scheduled = *self.state.scheduled_sample.lock().unwrap();
+        match scheduled {
+          Some(idx) => {
+            ui.label(format!("running #{}", idx + 1));
+          }
+          None => {
+            ui.label("idle");
+          }
+        }
+      });
       let mut to_delete: Option<usize> = None;
-      let mut play_events: Option<Vec<schema::TimedEvent>> = None;
+      let mut select: Option<Option<usize>> = None;
+      let mut activate: Option<usize> = None;
+      let mut quantize: Option<usize> = None;
+      let mut remove_event: Option<(usize, usize)> = None;
+      let mut retime_event: Option<(usize, usize, u64)> = None;
+      let mut remove_chord: Option<(usize, Vec<usize>)> = None;
+      let mut trim: Option<usize> = None;
+      let mut rebase: Option<usize> = None;
+      let mut reverse: Option<usize> = None;
+      let mut duplicate: Option<usize> = None;
+      let mut vary: Option<usize> = None;
+      let mut autofix: Option<usize> = None;
+      let mut dedup: Option<usize> = None;
+      let mut move_sample: Option<(usize, bool)> = None;
+      let mut play_sample: Option<state::Sample> = None;
+      let mut schedule: Option<Option<usize>> = None;
+      let mut concat: Option<(usize, usize)> = None;
+      let mut export_ahk: Option<usize> = None;
+      let mut export_csv: Option<usize> = None;
+      let mut export_midi: Option<usize> = None;
+      let mut export_png: Option<usize> = None;
+      let mut to_library: Option<usize> = None;
+      let active_idx = self.state.active_index();
       egui::ScrollArea::vertical().max_height(260.0).show(ui, |ui| {
         let mut samples = self.state.samples.lock().unwrap();
         for idx in 0..samples.len() {
-          ui.horizontal(|ui| {
-            ui.label(format!("#{}:", idx + 1));
+          if !samples[idx].matches(&self.sample_search) {
+            continue;
+          }
+          let row = ui.horizontal(|ui| {
+            ui.dnd_drag_source(egui::Id::new(("sample-drag", idx)), SampleDrag(idx), |ui| {
+              ui.label("☰");
+            })
+            .response
+            .on_hover_text("Drop onto another sample to append this one after it");
+            ui.checkbox(&mut samples[idx].checked, "")
+              .on_hover_text("Include in Merge Checked and Play Checked Layered");
+            let selected = *self.state.selected_sample.lock().unwrap() == Some(idx);
+            if ui
+              .selectable_label(selected, format!("#{}", idx + 1))
+              .on_hover_text("Select for step-through and editing tools")
+              .clicked()
+            {
+              select = Some(if selected { None } else { Some(idx) });
+            }
+            let active = active_idx == Some(idx);
+            let marker = egui::RichText::new(if active { "★" } else { "☆" });
+            if ui
+              .selectable_label(active, marker.color(Color32::YELLOW))
+              .on_hover_text("Active sample: the playback hotkey plays it")
+              .clicked()
+            {
+              activate = Some(idx);
+            }
             ui.add(
               egui::TextEdit::singleline(&mut samples[idx].name)
                 .desired_width(160.0),
             );
-            ui.label(format!("{} events", samples[idx].events.len()));
-            if ui.add_sized([36.0, 22.0], egui::Button::new("Up")).clicked() && idx > 0 {
-              samples.swap(idx - 1, idx);
+            match samples[idx].started_at {
+              Some(ms) => ui
+                .weak(state::format_timestamp(ms))
+                .on_hover_text("Recorded at (UTC)"),
+              None => ui.weak("—").on_hover_text("Recording time unknown"),
+            };
+            let mut remove_tag = None;
+            for (tag_idx, tag) in samples[idx].tags.iter().enumerate() {
+              if ui.small_button(format!("#{} x", tag)).on_hover_text("Remove tag").clicked() {
+                remove_tag = Some(tag_idx);
+              }
+            }
+            if let Some(tag_idx) = remove_tag {
+              samples[idx].tags.remove(tag_idx);
+            }
+            ui.label(format!(
+              "{} events, {:.1}s, {:.1}/s",
+              samples[idx].events.len(),
+              samples[idx].duration().as_secs_f64(),
+              samples[idx].event_rate()
+            ));
+            if ui.add_enabled(idx > 0, egui::Button::new("▲").small()).clicked() {
+              move_sample = Some((idx, true));
             }
             if ui
-              .add_sized([36.0, 22.0], egui::Button::new("Down"))
+              .add_enabled(idx + 1 < samples.len(), egui::Button::new("▼").small())
               .clicked()
-              && idx + 1 < samples.len()
             {
-              samples.swap(idx, idx + 1);
+              move_sample = Some((idx, false));
+            }
+            let issues = validate::validate_timeline(&samples[idx].events);
+            if !issues.is_empty() {
+              let details: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+              ui.colored_label(Color32::YELLOW, format!("⚠ {}", issues.len()))
+                .on_hover_text(details.join("\n"));
+              if ui.small_button("Auto-fix").on_hover_text("Release keys left held").clicked() {
+                autofix = Some(idx);
+              }
+              let repeated = issues
+                .iter()
+                .any(|i| matches!(i, validate::ValidationIssue::OverlappingHold { .. }));
+              if repeated
+                && ui
+                  .small_button("Dedup")
+                  .on_hover_text("Remove repeated presses of held keys")
+                  .clicked()
+              {
+                dedup = Some(idx);
+              }
+            }
+            ui.label("Offset:");
+            ui.add(
+              egui::DragValue::new(&mut samples[idx].offset_ms)
+                .speed(1)
+                .suffix(" ms"),
+            )
+            .on_hover_text("Added to the global playback offset");
+            let offset = self.state.playback_options(&samples[idx]).offset_ms;
+            let clipped = samples[idx].events_clipped_by(offset);
+            if clipped > 0 {
+              ui.colored_label(
+                Color32::YELLOW,
+                format!("offset clips {} events to t=0", clipped),
+              );
             }
             if ui.button("Play").clicked() {
-              play_events = Some(samples[idx].events.clone());
+              play_sample = Some(samples[idx].clone());
+            }
+            let scheduled = *self.state.scheduled_sample.lock().unwrap() == Some(idx);
+            let schedule_label = if scheduled { "Stop Scheduler" } else { "Start Scheduler" };
+            if ui
+              .button(schedule_label)
+              .on_hover_text("Replay this sample at the schedule interval")
+              .clicked()
+            {
+              schedule = Some(if scheduled { None } else { Some(idx) });
+            }
+            if ui.button("Trim").on_hover_text("Remove leading/trailing dead time").clicked() {
+              trim = Some(idx);
+            }
+            if ui
+              .button("Zero")
+              .on_hover_text("Move the first event to 0 ms, keeping the spacing")
+              .clicked()
+            {
+              rebase = Some(idx);
+            }
+            if ui.button("Reverse").on_hover_text("Add a backwards copy").clicked() {
+              reverse = Some(idx);
+            }
+            if ui.button("Duplicate").clicked() {
+              duplicate = Some(idx);
+            }
+            if ui
+              .button("Vary")
+              .on_hover_text("Add a copy with independent key presses reordered")
+              .clicked()
+            {
+              vary = Some(idx);
+            }
+            if ui.button("Quantize").clicked() {
+              quantize = Some(idx);
+            }
+            if ui.button("AHK").on_hover_text("Export as AutoHotkey script").clicked() {
+              export_ahk = Some(idx);
+            }
+            if ui.button("CSV").on_hover_text("Export as CSV").clicked() {
+              export_csv = Some(idx);
+            }
+            if ui
+              .button("MIDI")
+              .on_hover_text("Export as a MIDI file using the MIDI Notes mapping")
+              .clicked()
+            {
+              export_midi = Some(idx);
+            }
+            if ui
+              .button("PNG")
+              .on_hover_text("Export the timeline as an image")
+              .clicked()
+            {
+              export_png = Some(idx);
+            }
+            if ui
+              .button("To Library")
+              .on_hover_text("Save as its own file in the library folder")
+              .clicked()
+            {
+              to_library = Some(idx);
             }
             if ui.button("Delete").clicked() {
               to_delete = Some(idx);
             }
           });
-        }
-        if let Some(idx) = to_delete {
-          samples.remove(idx);
+          if let Some(dragged) = row.response.dnd_release_payload::<SampleDrag>()
+            && dragged.0 != idx
+          {
+            concat = Some((idx, dragged.0));
+          }
+          egui::CollapsingHeader::new(format!("Key Remap ({})", samples[idx].playback_remap.len()))
+            .id_source(("sample-remap", idx))
+            .show(ui, |ui| {
+              let remap = &mut samples[idx].playback_remap;
+              let mut mappings: Vec<(enigo::Key, enigo::Key)> =
+                remap.iter().map(|(&from, &to)| (from, to)).collect();
+              mappings.sort_by_key(|(from, _)| key_label(from));
+              for (from, to) in mappings {
+                ui.horizontal(|ui| {
+                  ui.label(format!("{} → {}", key_label(&from), key_label(&to)));
+                  if ui.small_button("x").clicked() {
+                    remap.remove(&from);
+                  }
+                });
+              }
+              ui.horizontal(|ui| {
+                ui.add(
+                  egui::TextEdit::singleline(&mut self.remap_from)
+                    .hint_text("w")
+                    .desired_width(60.0),
+                );
+                ui.label("→");
+                ui.add(
+                  egui::TextEdit::singleline(&mut self.remap_to)
+                    .hint_text("up")
+                    .desired_width(60.0),
+                );
+                if ui.button("Add").clicked() {
+                  let from = persist::string_to_key_loose(self.remap_from.trim());
+                  let to = persist::string_to_key_loose(self.remap_to.trim());
+                  match (from, to) {
+                    (Some(from), Some(to)) => {
+                      remap.insert(from, to);
+                      self.remap_from.clear();
+                      self.remap_to.clear();
+                    }
+                    _ => {
+                      self.file_status = format!(
+                        "Unknown key in `{}` → `{}`",
+                        self.remap_from.trim(),
+                        self.remap_to.trim()
+                      )
+                    }
+                  }
+                }
+              });
+            });
+          let muted_title = format!("Muted Keys ({})", samples[idx].muted_keys.len());
+          egui::CollapsingHeader::new(muted_title)
+            .id_source(("sample-muted", idx))
+            .show(ui, |ui| {
+              let sample = &mut samples[idx];
+              let mut keys: Vec<enigo::Key> = Vec::new();
+              for ev in &sample.events {
+                if let KeyAction::Down(k) | KeyAction::Up(k) = ev.action
+                  && !keys.contains(&k)
+                {
+                  keys.push(k);
+                }
+              }
+              keys.sort_by_key(key_label);
+              ui.horizontal_wrapped(|ui| {
+                for key in keys {
+                  let mut muted = sample.muted_keys.contains(&key);
+                  if ui
+                    .checkbox(&mut muted, key_label(&key))
+                    .on_hover_text("Keep in the recording but don't send during playback")
+                    .changed()
+                  {
+                    if muted {
+                      sample.muted_keys.insert(key);
+                    } else {
+                      sample.muted_keys.remove(&key);
+                    }
+                  }
+                }
+              });
+            });
+          egui::CollapsingHeader::new("Events")
+            .id_source(("sample-events", idx))
+            .show(ui, |ui| {
+              let chords = chords::detect_chords(&samples[idx].events, self.chord_window_ms);
+              let mut chord_iter = chords.iter().peekable();
+              let mut skip_to = 0;
+              let events = &samples[idx].events;
+              let times = |ui: &mut egui::Ui, ev_idx: usize| {
+                event_time_labels(ui, events, ev_idx, self.show_event_at, self.show_event_delta)
+              };
+              for (ev_idx, ev) in events.iter().enumerate() {
+                if ev_idx < skip_to {
+                  continue;
+                }
+                if let Some(chord) = chord_iter.next_if(|c| c.downs.start == ev_idx) {
+                  skip_to = chord.downs.end;
+                  ui.horizontal(|ui| {
+                    times(ui, ev_idx);
+                    ui.strong(format!("{} down", chord.label()));
+                    if ui
+                      .small_button("Delete")
+                      .on_hover_text("Delete the chord's presses and releases")
+                      .clicked()
+                    {
+                      remove_chord = Some((idx, chord.indices()));
+                    }
+                  });
+                  continue;
+                }
+                ui.horizontal(|ui| {
+                  if self.show_event_at {
+                    let mut at_ms = match self.event_edit {
+                      Some((s, e, ms)) if (s, e) == (idx, ev_idx) => ms,
+                      _ => ev.at.as_millis() as u64,
+                    };
+                    let drag = ui.add(egui::DragValue::new(&mut at_ms).speed(1).suffix(" ms"));
+                    if drag.changed() {
+                      self.event_edit = Some((idx, ev_idx, at_ms));
+                    }
+                    if drag.drag_stopped() || (drag.changed() && !drag.dragged()) {
+                      retime_event = Some((idx, ev_idx, at_ms));
+                      self.event_edit = None;
+                    }
+                  }
+                  event_time_labels(ui, events, ev_idx, false, self.show_event_delta);
+                  ui.label(action_label(&ev.action));
+                  if ui.small_button("Delete").clicked() {
+                    remove_event = Some((idx, ev_idx));
+                  }
+                });
+              }
+            });
         }
       });
-      if let Some(evs) = play_events {
-        self.state.playback_sample(&evs);
+      if let Some(idx) = to_delete {
+        self.state.delete_sample(idx);
+      }
+      if let Some(idx) = select {
+        self.state.select_sample(idx);
+      }
+      if let Some(idx) = activate {
+        *self.state.active_sample.lock().unwrap() = idx;
+      }
+      if let Some((idx, up)) = move_sample {
+        self.state.move_sample(idx, up);
+      }
+      if let Some(idx) = quantize {
+        self.state.quantize_sample(idx, self.quantize_bpm, self.quantize_division);
+      }
+      if let Some(idx) = autofix {
+        self.state.autofix_sample(idx);
+      }
+      if let Some(idx) = dedup {
+        self.state.dedup_sample(idx);
+      }
+      if let Some(idx) = trim {
+        self.state.trim_sample(idx);
       }
+      if let Some(idx) = rebase {
+        self.state.rebase_sample(idx);
+      }
+      if let Some(idx) = reverse {
+        self.state.reverse_sample(idx);
+      }
+      if let Some(idx) = duplicate {
+        self.state.clone_sample(idx);
+      }
+      if let Some(idx) = vary {
+        self.state.vary_sample(idx);
+      }
+      if let Some((sample_idx, ev_idx)) = remove_event {
+        self.state.remove_event(sample_idx, ev_idx);
+      }
+      if let Some((sample_idx, ev_idx, at_ms)) = retime_event {
+        self.state.set_event_time(sample_idx, ev_idx, at_ms);
+      }
+      if let Some((sample_idx, indices)) = remove_chord {
+        self.state.remove_events(sample_idx, &indices);
+      }
+      match schedule {
+        Some(Some(idx)) => self
+          .state
+          .start_scheduler(idx, Duration::from_secs_f64(self.schedule_interval_s)),
+        Some(None) => self.state.stop_scheduler(),
+        None => {}
+      }
+      if let Some((first, second)) = concat {
+        self.state.concat_samples(first, second, self.concat_gap_ms);
+      }
+      if let Some(sample) = play_sample {
+        self.state.playback_sample(&sample);
+      }
+      if let Some(idx) = export_ahk {
+        let path = export_path(&self.samples_path, idx, "ahk");
+        self.file_status = match self.state.export_sample_ahk(idx, &path) {
+          Ok(()) => format!("Exported {}", path.display()),
+          Err(e) => format!("Export failed: {}", e),
+        };
+      }
+      if let Some(idx) = export_csv {
+        let path = export_path(&self.samples_path, idx, "csv");
+        self.file_status = match self.state.export_sample_csv(idx, &path) {
+          Ok(()) => format!("Exported {}", path.display()),
+          Err(e) => format!("Export failed: {}", e),
+        };
+      }
+      if let Some(idx) = export_midi {
+        let path = export_path(&self.samples_path, idx, "mid");
+        self.file_status = match self.state.export_sample_midi(idx, &path, self.midi_bpm) {
+          Ok(()) => format!("Exported {}", path.display()),
+          Err(e) => format!("Export failed: {}", e),
+        };
+      }
+      if let Some(idx) = export_png {
+        let path = export_path(&self.samples_path, idx, "png");
+        let (width, height) = PNG_EXPORT_SIZE;
+        self.file_status = match self.state.export_sample_png(idx, &path, width, height) {
+          Ok(()) => format!("Exported {}", path.display()),
+          Err(e) => format!("Export failed: {}", e),
+        };
+      }
+      if let Some(idx) = to_library {
+        self.file_status = match self
+          .state
+          .save_sample_file(idx, Path::new(&self.library_dir))
+        {
+          Ok(path) => format!("Saved {}", path.display()),
+          Err(e) => format!("Save failed: {}", e),
+        };
+      }
+
+      egui::CollapsingHeader::new("Write Timeline").show(ui, |ui| {
+        ui.label("One event per line: `a@0` presses a at 0 ms, `a^@80` releases it.");
+        ui.add(
+          egui::TextEdit::multiline(&mut self.text_timeline)
+            .code_editor()
+            .desired_rows(4)
+            .hint_text("a@0\na^@80\nspace@150\nspace^@230"),
+        );
+        if ui.button("Add as Sample").clicked() {
+          self.file_status = match self.state.import_text(&self.text_timeline) {
+            Ok(n) => format!("Added sample with {} events", n),
+            Err(e) => format!("Timeline error: {}", e),
+          };
+        }
+        ui.horizontal(|ui| {
+          ui.label("Hold");
+          ui.add(egui::TextEdit::singleline(&mut self.hold_key).desired_width(60.0));
+          ui.label("at");
+          ui.add(egui::DragValue::new(&mut self.hold_start_ms).suffix(" ms"));
+          ui.label("for");
+          ui.add(egui::DragValue::new(&mut self.hold_duration_ms).suffix(" ms"));
+          let selected = *self.state.selected_sample.lock().unwrap();
+          if ui
+            .add_enabled(selected.is_some(), egui::Button::new("Add to Selected"))
+            .clicked()
+            && let Some(idx) = selected
+          {
+            match persist::string_to_key_loose(self.hold_key.trim()) {
+              Some(key) => {
+                let pair = text_timeline::hold(key, self.hold_start_ms, self.hold_duration_ms);
+                self.state.insert_events(idx, &pair);
+              }
+              None => self.file_status = format!("Unknown key `{}`", self.hold_key.trim()),
+            }
+          }
+        });
+      });
+
+      ui.separator();
+      egui::CollapsingHeader::new("Timeline").show(ui, |ui| {
+        let selected = *self.state.selected_sample.lock().unwrap();
+        let sample = selected.and_then(|idx| self.state.samples.lock().unwrap().get(idx).cloned());
+        let (Some(idx), Some(sample)) = (selected, sample) else {
+          ui.label("Select a sample to view its timeline.");
+          return;
+        };
+        let end_ms = sample.duration().as_millis() as u64;
+        self.scrub_ms = self.scrub_ms.min(end_ms);
+        ui.horizontal(|ui| {
+          ui.label("Zoom:");
+          ui.add(
+            egui::DragValue::new(&mut self.timeline_zoom)
+              .speed(1.0)
+              .clamp_range(10.0..=2000.0)
+              .suffix(" px/s"),
+          );
+        });
+        ui.horizontal(|ui| {
+          ui.label("Start at:");
+          ui.add(egui::Slider::new(&mut self.scrub_ms, 0..=end_ms).suffix(" ms"));
+          if ui.button("Play From Here").clicked() {
+            self.state.playback_sample_from(&sample, self.scrub_ms);
+          }
+        });
+        let mut region = sample.loop_region;
+        let mut region_changed = false;
+        ui.horizontal(|ui| {
+          let mut looping = region.is_some();
+          if ui
+            .checkbox(&mut looping, "Loop region")
+            .on_hover_text("Play only the shaded section; drag its edges on the timeline")
+            .changed()
+          {
+            region = looping.then(|| (Duration::ZERO, sample.duration()));
+            region_changed = true;
+          }
+          if let Some((start, end)) = region {
+            ui.label(format!("{} – {} ms", start.as_millis(), end.as_millis()));
+          }
+        });
+        let playhead = self
+          .state
+          .playback_position()
+          .or((self.scrub_ms > 0).then(|| Duration::from_millis(self.scrub_ms)));
+        region_changed |= timeline_view::show(
+          ui,
+          "timeline-scroll",
+          &sample.events,
+          self.timeline_zoom,
+          playhead,
+          &mut region,
+        );
+        if region_changed && let Some(s) = self.state.samples.lock().unwrap().get_mut(idx) {
+          s.loop_region = region;
+        }
+      });
+
+      ui.separator();
+      egui::CollapsingHeader::new("MIDI Notes").show(ui, |ui| {
+        let selected = *self.state.selected_sample.lock().unwrap();
+        let keys = selected
+          .and_then(|idx| {
+            let samples = self.state.samples.lock().unwrap();
+            let sample = samples.get(idx)?;
+            let mut keys: Vec<enigo::Key> = Vec::new();
+            for ev in &sample.events {
+              if let KeyAction::Down(k) = ev.action
+                && !keys.contains(&k)
+              {
+                keys.push(k);
+              }
+            }
+            Some(keys)
+          })
+          .unwrap_or_default();
+        if keys.is_empty() {
+          ui.label("Select a sample with key presses to map them to notes.");
+          return;
+        }
+        ui.horizontal(|ui| {
+          ui.label("Tempo:");
+          ui.add(
+            egui::DragValue::new(&mut self.midi_bpm)
+              .clamp_range(20.0..=400.0)
+              .suffix(" bpm"),
+          );
+        });
+        egui::Grid::new("midi-notes").show(ui, |ui| {
+          let mut notes = self.state.midi_notes.lock().unwrap();
+          for key in keys {
+            let mut mapped = notes.contains_key(&key);
+            if ui.checkbox(&mut mapped, key_label(&key)).changed() {
+              if mapped {
+                notes.insert(key, 60);
+              } else {
+                notes.remove(&key);
+              }
+            }
+            if let Some(note) = notes.get_mut(&key) {
+              ui.add(egui::DragValue::new(note).clamp_range(0..=127).prefix("note "));
+            }
+            ui.end_row();
+          }
+        });
+      });
+
+      ui.separator();
+      egui::CollapsingHeader::new("Gamepad Keys").show(ui, |ui| {
+        ui.label("Recorded controller buttons play back as these keys.");
+        let mut mapping = self.state.gamepad_keys.lock().unwrap();
+        let mut mapped: Vec<(GamepadButton, enigo::Key)> =
+          mapping.iter().map(|(&b, &k)| (b, k)).collect();
+        mapped.sort_by_key(|(button, _)| *button);
+        for (button, key) in mapped {
+          ui.horizontal(|ui| {
+            ui.label(format!("{:?} → {}", button, key_label(&key)));
+            if ui.small_button("x").clicked() {
+              mapping.remove(&button);
+            }
+          });
+        }
+        ui.horizontal(|ui| {
+          egui::ComboBox::from_id_source("gamepad-button")
+            .selected_text(format!("{:?}", self.gamepad_button))
+            .show_ui(ui, |ui| {
+              for button in persist::gamepad_buttons() {
+                ui.selectable_value(&mut self.gamepad_button, button, format!("{:?}", button));
+              }
+            });
+          ui.label("→");
+          ui.add(
+            egui::TextEdit::singleline(&mut self.gamepad_key)
+              .hint_text("space")
+              .desired_width(60.0),
+          );
+          if ui.button("Add").clicked() {
+            match persist::string_to_key_loose(self.gamepad_key.trim()) {
+              Some(key) => {
+                mapping.insert(self.gamepad_button, key);
+                self.gamepad_key.clear();
+              }
+              None => self.file_status = format!("Unknown key `{}`", self.gamepad_key.trim()),
+            }
+          }
+        });
+      });
+
+      ui.separator();
+      egui::CollapsingHeader::new("Timing Stats").show(ui, |ui| {
+        ui.horizontal(|ui| {
+          ui.label("Outliers:");
+          let mut method = *self.state.outlier_method.lock().unwrap();
+          ui.radio_value(&mut method, OutlierMethod::None, "None");
+          if ui
+            .radio(matches!(method, OutlierMethod::StdDev { .. }), "Std-dev")
+            .clicked()
+            && !matches!(method, OutlierMethod::StdDev { .. })
+          {
+            method = OutlierMethod::StdDev { k: 2.0 };
+          }
+          if ui
+            .radio(matches!(method, OutlierMethod::Iqr { .. }), "IQR")
+            .clicked()
+            && !matches!(method, OutlierMethod::Iqr { .. })
+          {
+            method = OutlierMethod::Iqr { factor: 1.5 };
+          }
+          match &mut method {
+            OutlierMethod::StdDev { k } => {
+              ui.add(egui::DragValue::new(k).prefix("k = ").speed(0.05).clamp_range(0.1..=10.0));
+            }
+            OutlierMethod::Iqr { factor } => {
+              ui.add(
+                egui::DragValue::new(factor)
+                  .prefix("factor = ")
+                  .speed(0.05)
+                  .clamp_range(0.1..=10.0),
+              );
+            }
+            OutlierMethod::None => {}
+          }
+          *self.state.outlier_method.lock().unwrap() = method;
+        });
+        if ui
+          .button("Build Averaged Sample")
+          .on_hover_text("New sample with each slot at its mean time")
+          .clicked()
+        {
+          self.state.build_averaged_sample();
+        }
+        ui.horizontal(|ui| {
+          if ui
+            .button("Export JSON")
+            .on_hover_text("Write these stats next to the samples file")
+            .clicked()
+          {
+            let path = sibling_path(&self.samples_path, "stats", "json");
+            self.file_status = match self.state.export_stats(&path, self.stats_raw) {
+              Ok(()) => format!("Exported {}", path.display()),
+              Err(e) => format!("Export failed: {}", e),
+            };
+          }
+          ui.checkbox(&mut self.stats_raw, "Include raw values");
+        });
+        let stats = self.state.timing_stats();
+        if stats.is_empty() {
+          ui.label("No key events recorded yet.");
+          return;
+        }
+        egui::ScrollArea::vertical()
+          .id_source("stats-scroll")
+          .max_height(200.0)
+          .show(ui, |ui| {
+            egui::Grid::new("stats-grid").striped(true).show(ui, |ui| {
+              for header in ["Key", "Mode", "#", "Mean", "Median", "Std", "Min", "Max", "n"] {
+                ui.strong(header);
+              }
+              ui.end_row();
+              for slot in &stats {
+                ui.label(key_label(&slot.key));
+                ui.label(format!("{:?}", slot.mode));
+                ui.label(slot.index.to_string());
+                for v in [slot.mean, slot.median, slot.std, slot.min, slot.max] {
+                  ui.label(format!("{:.1}", v));
+                }
+                ui.label(slot.n.to_string());
+                ui.end_row();
+              }
+            });
+          });
+      });
+
+      ui.separator();
+      egui::CollapsingHeader::new("Log").show(ui, |ui| {
+        if ui.small_button("Clear").clicked() {
+          self.state.log_lines.lock().unwrap().clear();
+        }
+        egui::ScrollArea::vertical()
+          .id_source("log-scroll")
+          .max_height(160.0)
+          .stick_to_bottom(true)
+          .show(ui, |ui| {
+            for line in self.state.log_lines.lock().unwrap().iter() {
+              ui.monospace(line);
+            }
+          });
+      });
     });
 
+    let pending = self.state.pending_playback.lock().unwrap().clone();
+    if let Some(sample) = pending {
+      egui::Window::new("Different window in front")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+          ui.label(format!(
+            "{} was recorded in \"{}\".",
+            sample.name,
+            sample.window_title.as_deref().unwrap_or_default()
+          ));
+          ui.label("Play it into the current window anyway?");
+          ui.horizontal(|ui| {
+            if ui.button("Play").clicked() {
+              self.state.confirm_pending_playback();
+            }
+            if ui.button("Cancel").clicked() {
+              self.state.cancel_pending_playback();
+            }
+          });
+        });
+    }
+
     // Overlay window for key viewer
     if self.overlay_open {
       ctx.show_viewport_immediate(
@@ -172,19 +1907,15 @@ impl eframe::App for RecorderApp {
           egui::CentralPanel::default()
             .frame(egui::Frame::none().fill(Color32::TRANSPARENT))
             .show(overlay_ctx, |ui| {
+              let look = &self.overlay_style;
               egui::ScrollArea::horizontal()
-                .max_height(80.0)
+                .max_height(look.key_size.y + 46.0)
                 .show(ui, |ui| {
                   ui.horizontal(|ui| {
                     for (key, pressed) in self.state.tracked_key_states() {
-                      let fill = if pressed {
-                        Color32::from_rgb(120, 220, 120)
-                      } else {
-                        Color32::from_rgb(60, 60, 60)
-                      };
+                      let fill = if pressed { look.pressed } else { look.released };
                       let stroke = egui::Stroke::new(1.0, Color32::from_rgb(200, 200, 200));
-                      let (rect, _resp) =
-                        ui.allocate_exact_size(egui::vec2(60.0, 34.0), egui::Sense::hover());
+                      let (rect, _resp) = ui.allocate_exact_size(look.key_size, egui::Sense::hover());
                       ui.painter()
                         .rect(rect, 6.0, fill, stroke);
                       ui.painter().text(
@@ -192,7 +1923,7 @@ impl eframe::App for RecorderApp {
                         egui::Align2::CENTER_CENTER,
                         key_label(&key),
                         egui::TextStyle::Button.resolve(ui.style()),
-                        Color32::BLACK,
+                        look.text,
                       );
                     }
                   });
@@ -203,3 +1934,68 @@ impl eframe::App for RecorderApp {
     }
   }
 }
+
+/// `+N` seconds from now, or `HH:MM[:SS]` UTC today (tomorrow if that has passed).
+fn parse_start_time(text: &str) -> Option<SystemTime> {
+  let text = text.trim();
+  if let Some(secs) = text.strip_prefix('+') {
+    let secs: f64 = secs.trim().parse().ok()?;
+    return (secs.is_finite() && secs >= 0.0)
+      .then(|| SystemTime::now() + Duration::from_secs_f64(secs));
+  }
+  let mut parts = text.split(':').map(|p| p.trim().parse::<u64>().ok());
+  let hour = parts.next()??;
+  let minute = parts.next()??;
+  let second = parts.next().unwrap_or(Some(0))?;
+  if parts.next().is_some() || hour >= 24 || minute >= 60 || second >= 60 {
+    return None;
+  }
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+  let mut at = now - now % 86_400 + hour * 3600 + minute * 60 + second;
+  if at <= now {
+    at += 86_400;
+  }
+  Some(UNIX_EPOCH + Duration::from_secs(at))
+}
+
+/// `mm:ss.mmm`, e.g. `01:07.250`.
+fn format_elapsed(elapsed: Duration) -> String {
+  let ms = elapsed.as_millis();
+  format!("{:02}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+/// The event list's time columns for `events[idx]`: its `at` and/or the signed gap
+/// since the event before it.
+fn event_time_labels(
+  ui: &mut egui::Ui,
+  events: &[auto_note::TimedEvent],
+  idx: usize,
+  absolute: bool,
+  delta: bool,
+) {
+  let at_ms = events[idx].at.as_millis() as i64;
+  if absolute {
+    ui.label(format!("{:>6} ms", at_ms));
+  }
+  if delta {
+    let prev_ms = idx
+      .checked_sub(1)
+      .map_or(0, |prev| events[prev].at.as_millis() as i64);
+    ui.monospace(format!("{:>+6} ms", at_ms - prev_ms));
+  }
+}
+
+/// Path next to the samples file for exporting sample `idx`, e.g. `samples-2.ahk`.
+fn export_path(samples_path: &str, idx: usize, ext: &str) -> PathBuf {
+  sibling_path(samples_path, &(idx + 1).to_string(), ext)
+}
+
+/// `samples_path` with `-suffix.ext` in place of its extension, e.g. `samples-stats.json`.
+fn sibling_path(samples_path: &str, suffix: &str, ext: &str) -> PathBuf {
+  let base = Path::new(samples_path);
+  let stem = base
+    .file_stem()
+    .and_then(|s| s.to_str())
+    .unwrap_or("samples");
+  base.with_file_name(format!("{}-{}.{}", stem, suffix, ext))
+}
@@ -0,0 +1,772 @@
+use crate::schema::{GamepadButton, KeyAction, TimedEvent};
+use crate::state::Sample;
+use enigo::{Key, MouseButton};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Current on-disk format version written by `SaveFile`. Version 2 added `at_us`.
+pub const SAVE_VERSION: u32 = 2;
+
+/// Save files with this extension use the compact binary format; anything else is JSON.
+pub const BINARY_EXTENSION: &str = "bin";
+
+/// Top-level save file. Version 0 is the legacy bare array of samples.
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+  pub version: u32,
+  pub samples: Vec<SerializableSample>,
+}
+
+/// Samples read back from a save file.
+pub struct LoadedFile {
+  pub samples: Vec<Sample>,
+  /// Events dropped because this build can't map their key.
+  pub skipped: usize,
+  /// Format version the file was written with; 0 is the legacy bare array.
+  pub version: u32,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+  Io(io::Error),
+  Parse(serde_json::Error),
+  Binary(bincode::Error),
+  /// The file's format version is newer than `SAVE_VERSION`.
+  NewerVersion(u32),
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LoadError::Io(e) => write!(f, "{}", e),
+      LoadError::Parse(e) => write!(f, "unreadable save file: {}", e),
+      LoadError::Binary(e) => write!(f, "unreadable binary save file: {}", e),
+      LoadError::NewerVersion(v) => write!(
+        f,
+        "This file was saved by a newer version of Auto Note (format version {})",
+        v
+      ),
+    }
+  }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+  fn from(e: io::Error) -> Self {
+    LoadError::Io(e)
+  }
+}
+
+impl From<serde_json::Error> for LoadError {
+  fn from(e: serde_json::Error) -> Self {
+    LoadError::Parse(e)
+  }
+}
+
+impl From<bincode::Error> for LoadError {
+  fn from(e: bincode::Error) -> Self {
+    LoadError::Binary(e)
+  }
+}
+
+impl SaveFile {
+  pub fn new(samples: Vec<SerializableSample>) -> Self {
+    Self { version: SAVE_VERSION, samples }
+  }
+
+  /// Parse a save file, migrating the legacy bare-array form.
+  pub fn parse(data: &str) -> Result<Self, LoadError> {
+    let value: serde_json::Value = serde_json::from_str(data)?;
+    if value.is_array() {
+      let samples = serde_json::from_value(value)?;
+      return Ok(Self { version: 0, samples });
+    }
+    // Check before deserializing so a newer layout reports its version, not a parse error.
+    if let Some(version) = value.get("version").and_then(|v| v.as_u64())
+      && version > SAVE_VERSION as u64
+    {
+      return Err(LoadError::NewerVersion(version.min(u32::MAX as u64) as u32));
+    }
+    Ok(serde_json::from_value(value)?)
+  }
+}
+
+/// Write `samples` to `path`; `micros` keeps microsecond timestamps. Returns how many
+/// events were skipped because their key has no stable name.
+pub fn save_file(path: &Path, samples: &[Sample], micros: bool) -> io::Result<usize> {
+  let mut skipped = 0;
+  let serializable: Vec<SerializableSample> = samples
+    .iter()
+    .map(|s| {
+      let (ser, n) = SerializableSample::from_sample(s, micros);
+      skipped += n;
+      ser
+    })
+    .collect();
+  let save = SaveFile::new(serializable);
+  if is_binary(path) {
+    let bytes = bincode::serialize(&BinFile::from(save)).map_err(io::Error::other)?;
+    fs::write(path, bytes)?;
+  } else {
+    fs::write(path, serde_json::to_string_pretty(&save)?)?;
+  }
+  Ok(skipped)
+}
+
+/// Read a save file of any version, binary if the path ends in `.bin`.
+pub fn load_file(path: &Path) -> Result<LoadedFile, LoadError> {
+  let save = if is_binary(path) {
+    let bytes = fs::read(path)?;
+    // The version is the leading field, so it can be read on its own.
+    let version: u32 = bincode::deserialize(&bytes)?;
+    if version > SAVE_VERSION {
+      return Err(LoadError::NewerVersion(version));
+    }
+    let bin: BinFile = bincode::deserialize(&bytes)?;
+    SaveFile::from(bin)
+  } else {
+    SaveFile::parse(&fs::read_to_string(path)?)?
+  };
+  let mut skipped = 0;
+  let samples = save
+    .samples
+    .into_iter()
+    .map(|s| {
+      let (sample, n) = s.into_sample();
+      skipped += n;
+      sample
+    })
+    .collect();
+  Ok(LoadedFile {
+    samples,
+    skipped,
+    version: save.version,
+  })
+}
+
+/// Whether `path` holds a save file written by a newer version of the format.
+pub fn is_newer_version(path: &Path) -> bool {
+  matches!(load_file(path), Err(LoadError::NewerVersion(_)))
+}
+
+fn is_binary(path: &Path) -> bool {
+  path.extension().is_some_and(|ext| ext == BINARY_EXTENSION)
+}
+
+// bincode can't handle the JSON types' flattened, internally tagged actions or their
+// skipped fields, so the binary format mirrors them with plain structs.
+
+#[derive(Serialize, Deserialize)]
+struct BinFile {
+  version: u32,
+  samples: Vec<BinSample>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinSample {
+  name: String,
+  events: Vec<BinEvent>,
+  offset_ms: i64,
+  tags: Vec<String>,
+  window_title: Option<String>,
+  started_at: Option<u64>,
+  loop_region_us: Option<(u64, u64)>,
+  remap: BTreeMap<String, String>,
+  muted: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinEvent {
+  at_us: u64,
+  action: BinAction,
+}
+
+#[derive(Serialize, Deserialize)]
+enum BinAction {
+  Press(String),
+  Release(String),
+  MouseMove(f64, f64),
+  ButtonPress(String),
+  ButtonRelease(String),
+  Type(String),
+  GamepadPress(String),
+  GamepadRelease(String),
+}
+
+impl From<SaveFile> for BinFile {
+  fn from(save: SaveFile) -> Self {
+    let samples = save
+      .samples
+      .into_iter()
+      .map(|s| BinSample {
+        name: s.name,
+        events: s
+          .events
+          .into_iter()
+          .map(|ev| BinEvent {
+            at_us: ev.at_us.or(ev.delta_ms.map(|ms| ms * 1000)).unwrap_or(0),
+            action: match ev.action {
+              SerializableAction::Press { key } => BinAction::Press(key),
+              SerializableAction::Release { key } => BinAction::Release(key),
+              SerializableAction::MouseMove { x, y } => BinAction::MouseMove(x, y),
+              SerializableAction::ButtonPress { button } => BinAction::ButtonPress(button),
+              SerializableAction::ButtonRelease { button } => BinAction::ButtonRelease(button),
+              SerializableAction::Type { text } => BinAction::Type(text),
+              SerializableAction::GamepadPress { button } => BinAction::GamepadPress(button),
+              SerializableAction::GamepadRelease { button } => BinAction::GamepadRelease(button),
+            },
+          })
+          .collect(),
+        offset_ms: s.offset_ms,
+        tags: s.tags,
+        window_title: s.window_title,
+        started_at: s.started_at,
+        loop_region_us: s.loop_region_us,
+        remap: s.remap,
+        muted: s.muted,
+      })
+      .collect();
+    Self {
+      version: save.version,
+      samples,
+    }
+  }
+}
+
+impl From<BinFile> for SaveFile {
+  fn from(bin: BinFile) -> Self {
+    let samples = bin
+      .samples
+      .into_iter()
+      .map(|s| SerializableSample {
+        name: s.name,
+        events: s
+          .events
+          .into_iter()
+          .map(|ev| SerializableEvent {
+            delta_ms: None,
+            at_us: Some(ev.at_us),
+            action: match ev.action {
+              BinAction::Press(key) => SerializableAction::Press { key },
+              BinAction::Release(key) => SerializableAction::Release { key },
+              BinAction::MouseMove(x, y) => SerializableAction::MouseMove { x, y },
+              BinAction::ButtonPress(button) => SerializableAction::ButtonPress { button },
+              BinAction::ButtonRelease(button) => SerializableAction::ButtonRelease { button },
+              BinAction::Type(text) => SerializableAction::Type { text },
+              BinAction::GamepadPress(button) => SerializableAction::GamepadPress { button },
+              BinAction::GamepadRelease(button) => SerializableAction::GamepadRelease { button },
+            },
+          })
+          .collect(),
+        offset_ms: s.offset_ms,
+        tags: s.tags,
+        window_title: s.window_title,
+        started_at: s.started_at,
+        loop_region_us: s.loop_region_us,
+        remap: s.remap,
+        muted: s.muted,
+      })
+      .collect();
+    Self {
+      version: bin.version,
+      samples,
+    }
+  }
+}
+
+/// Write one sample as `{name}.json` inside `dir`, adding `-2`, `-3`, ... if that
+/// name is taken. Returns the path written and the number of events skipped.
+pub fn save_sample_file(dir: &Path, sample: &Sample, micros: bool) -> io::Result<(PathBuf, usize)> {
+  fs::create_dir_all(dir)?;
+  let stem = sanitize_file_name(&sample.name);
+  let mut path = dir.join(format!("{}.json", stem));
+  let mut counter = 2;
+  while path.exists() {
+    path = dir.join(format!("{}-{}.json", stem, counter));
+    counter += 1;
+  }
+  let (ser, skipped) = SerializableSample::from_sample(sample, micros);
+  fs::write(&path, serde_json::to_string_pretty(&ser)?)?;
+  Ok((path, skipped))
+}
+
+/// Read a single-sample file written by `save_sample_file`.
+pub fn load_sample_file(path: &Path) -> Result<(Sample, usize), LoadError> {
+  let data = fs::read_to_string(path)?;
+  let ser: SerializableSample = serde_json::from_str(&data)?;
+  Ok(ser.into_sample())
+}
+
+/// Every `*.json` file in `dir`, sorted by file name.
+pub fn library_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+  let mut files = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+      files.push(path);
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+/// Turn a sample name into a file stem that stays inside its directory: path
+/// separators and other characters Windows rejects become `_`.
+pub fn sanitize_file_name(name: &str) -> String {
+  let cleaned: String = name
+    .chars()
+    .map(|c| match c {
+      '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect();
+  let trimmed = cleaned.trim().trim_matches('.');
+  if trimmed.is_empty() {
+    "sample".to_string()
+  } else {
+    trimmed.to_string()
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableSample {
+  pub name: String,
+  pub events: Vec<SerializableEvent>,
+  #[serde(default)]
+  pub offset_ms: i64,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub window_title: Option<String>,
+  /// Recording start in ms since the Unix epoch.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub started_at: Option<u64>,
+  /// Loop region as `[start_us, end_us]`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub loop_region_us: Option<(u64, u64)>,
+  /// Playback key remap, recorded key name to sent key name.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub remap: BTreeMap<String, String>,
+  /// Names of keys not sent during playback.
+  #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+  pub muted: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableEvent {
+  /// Millisecond timestamp; the only timing field before version 2.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub delta_ms: Option<u64>,
+  /// Microsecond timestamp, written instead of `delta_ms` when saving with full precision.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub at_us: Option<u64>,
+  #[serde(flatten)]
+  pub action: SerializableAction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SerializableAction {
+  Press { key: String },
+  Release { key: String },
+  MouseMove { x: f64, y: f64 },
+  ButtonPress { button: String },
+  ButtonRelease { button: String },
+  Type { text: String },
+  GamepadPress { button: String },
+  GamepadRelease { button: String },
+}
+
+/// Named (non-character) keys and their stable on-disk names.
+const NAMED_KEYS: &[(&str, Key)] = &[
+  ("Space", Key::Space),
+  ("Return", Key::Return),
+  ("Backspace", Key::Backspace),
+  ("Tab", Key::Tab),
+  ("Escape", Key::Escape),
+  ("UpArrow", Key::UpArrow),
+  ("DownArrow", Key::DownArrow),
+  ("LeftArrow", Key::LeftArrow),
+  ("RightArrow", Key::RightArrow),
+  ("Shift", Key::Shift),
+  ("Control", Key::Control),
+  ("Alt", Key::Alt),
+  ("Meta", Key::Meta),
+  ("CapsLock", Key::CapsLock),
+  ("Delete", Key::Delete),
+  ("Home", Key::Home),
+  ("End", Key::End),
+  ("PageUp", Key::PageUp),
+  ("PageDown", Key::PageDown),
+  #[cfg(any(target_os = "windows", target_os = "linux"))]
+  ("Insert", Key::Insert),
+  #[cfg(any(target_os = "windows", target_os = "linux"))]
+  ("PrintScreen", Key::Print),
+  #[cfg(any(target_os = "windows", target_os = "linux"))]
+  ("Pause", Key::Pause),
+  #[cfg(any(target_os = "windows", target_os = "linux"))]
+  ("NumLock", Key::Numlock),
+  #[cfg(target_os = "linux")]
+  ("ScrollLock", Key::ScrollLock),
+  #[cfg(target_os = "windows")]
+  ("ScrollLock", Key::Scroll),
+  ("F1", Key::F1),
+  ("F2", Key::F2),
+  ("F3", Key::F3),
+  ("F4", Key::F4),
+  ("F5", Key::F5),
+  ("F6", Key::F6),
+  ("F7", Key::F7),
+  ("F8", Key::F8),
+  ("F9", Key::F9),
+  ("F10", Key::F10),
+  ("F11", Key::F11),
+  ("F12", Key::F12),
+];
+
+const BUTTONS: &[(&str, MouseButton)] = &[
+  ("Left", MouseButton::Left),
+  ("Right", MouseButton::Right),
+  ("Middle", MouseButton::Middle),
+];
+
+const GAMEPAD_BUTTONS: &[(&str, GamepadButton)] = &[
+  ("South", GamepadButton::South),
+  ("East", GamepadButton::East),
+  ("North", GamepadButton::North),
+  ("West", GamepadButton::West),
+  ("LeftBumper", GamepadButton::LeftBumper),
+  ("RightBumper", GamepadButton::RightBumper),
+  ("LeftTrigger", GamepadButton::LeftTrigger),
+  ("RightTrigger", GamepadButton::RightTrigger),
+  ("Select", GamepadButton::Select),
+  ("Start", GamepadButton::Start),
+  ("Mode", GamepadButton::Mode),
+  ("LeftThumb", GamepadButton::LeftThumb),
+  ("RightThumb", GamepadButton::RightThumb),
+  ("DPadUp", GamepadButton::DPadUp),
+  ("DPadDown", GamepadButton::DPadDown),
+  ("DPadLeft", GamepadButton::DPadLeft),
+  ("DPadRight", GamepadButton::DPadRight),
+];
+
+/// Prefix for raw platform key codes, e.g. `Raw:93`.
+const RAW_PREFIX: &str = "Raw:";
+
+/// Layout keys are stored as the character itself, raw codes as `Raw:<code>` and
+/// named keys by name.
+pub fn key_to_string(key: Key) -> Option<String> {
+  match key {
+    Key::Layout(c) => return Some(c.to_string()),
+    Key::Raw(code) => return Some(format!("{}{}", RAW_PREFIX, code)),
+    _ => {}
+  }
+  NAMED_KEYS
+    .iter()
+    .find(|(_, k)| *k == key)
+    .map(|(name, _)| name.to_string())
+}
+
+pub fn string_to_key(s: &str) -> Option<Key> {
+  let mut chars = s.chars();
+  if let (Some(c), None) = (chars.next(), chars.next()) {
+    return Some(Key::Layout(c));
+  }
+  if let Some(code) = s.strip_prefix(RAW_PREFIX) {
+    return code.parse().ok().map(Key::Raw);
+  }
+  NAMED_KEYS
+    .iter()
+    .find(|(name, _)| *name == s)
+    .map(|(_, k)| *k)
+}
+
+/// Like `string_to_key`, but named keys match regardless of case (`space`, `SPACE`).
+pub fn string_to_key_loose(s: &str) -> Option<Key> {
+  string_to_key(s).or_else(|| {
+    NAMED_KEYS
+      .iter()
+      .find(|(name, _)| name.eq_ignore_ascii_case(s))
+      .map(|(_, k)| *k)
+  })
+}
+
+pub fn button_to_string(button: MouseButton) -> Option<String> {
+  BUTTONS
+    .iter()
+    .find(|(_, b)| *b == button)
+    .map(|(name, _)| name.to_string())
+}
+
+pub fn string_to_button(s: &str) -> Option<MouseButton> {
+  BUTTONS.iter().find(|(name, _)| *name == s).map(|(_, b)| *b)
+}
+
+pub fn gamepad_button_to_string(button: GamepadButton) -> &'static str {
+  GAMEPAD_BUTTONS
+    .iter()
+    .find(|(_, b)| *b == button)
+    .map_or("Unknown", |(name, _)| name)
+}
+
+pub fn string_to_gamepad_button(s: &str) -> Option<GamepadButton> {
+  GAMEPAD_BUTTONS
+    .iter()
+    .find(|(name, _)| *name == s)
+    .map(|(_, b)| *b)
+}
+
+/// Every controller button, in table order.
+pub fn gamepad_buttons() -> impl Iterator<Item = GamepadButton> {
+  GAMEPAD_BUTTONS.iter().map(|(_, b)| *b)
+}
+
+impl SerializableSample {
+  /// Returns the sample and the number of events whose key could not be stored.
+  /// With `micros` the timestamps keep microsecond precision.
+  pub fn from_sample(sample: &Sample, micros: bool) -> (Self, usize) {
+    let mut skipped = 0;
+    let events = sample
+      .events
+      .iter()
+      .filter_map(|ev| {
+        let action = match ev.action {
+          KeyAction::Down(k) => key_to_string(k).map(|key| SerializableAction::Press { key }),
+          KeyAction::Up(k) => key_to_string(k).map(|key| SerializableAction::Release { key }),
+          KeyAction::MouseMove { x, y } => Some(SerializableAction::MouseMove { x, y }),
+          KeyAction::ButtonDown(b) => {
+            button_to_string(b).map(|button| SerializableAction::ButtonPress { button })
+          }
+          KeyAction::ButtonUp(b) => {
+            button_to_string(b).map(|button| SerializableAction::ButtonRelease { button })
+          }
+          KeyAction::Type(c) => Some(SerializableAction::Type {
+            text: c.to_string(),
+          }),
+          KeyAction::GamepadDown(b) => Some(SerializableAction::GamepadPress {
+            button: gamepad_button_to_string(b).to_string(),
+          }),
+          KeyAction::GamepadUp(b) => Some(SerializableAction::GamepadRelease {
+            button: gamepad_button_to_string(b).to_string(),
+          }),
+        };
+        if action.is_none() {
+          skipped += 1;
+        }
+        action.map(|action| SerializableEvent {
+          delta_ms: (!micros).then_some(ev.at.as_millis() as u64),
+          at_us: micros.then_some(ev.at.as_micros() as u64),
+          action,
+        })
+      })
+      .collect();
+    (
+      Self {
+        name: sample.name.clone(),
+        events,
+        offset_ms: sample.offset_ms,
+        tags: sample.tags.clone(),
+        window_title: sample.window_title.clone(),
+        started_at: sample.started_at,
+        loop_region_us: sample
+          .loop_region
+          .map(|(start, end)| (start.as_micros() as u64, end.as_micros() as u64)),
+        remap: sample
+          .playback_remap
+          .iter()
+          .filter_map(|(&from, &to)| Some((key_to_string(from)?, key_to_string(to)?)))
+          .collect(),
+        muted: sample
+          .muted_keys
+          .iter()
+          .filter_map(|&k| key_to_string(k))
+          .collect(),
+      },
+      skipped,
+    )
+  }
+
+  /// Returns the sample and the number of events whose key this build can't map.
+  pub fn into_sample(self) -> (Sample, usize) {
+    let mut skipped = 0;
+    let events = self
+      .events
+      .into_iter()
+      .filter_map(|ev| {
+        let action = match ev.action {
+          SerializableAction::Press { key } => string_to_key(&key).map(KeyAction::Down),
+          SerializableAction::Release { key } => string_to_key(&key).map(KeyAction::Up),
+          SerializableAction::MouseMove { x, y } => Some(KeyAction::MouseMove { x, y }),
+          SerializableAction::ButtonPress { button } => {
+            string_to_button(&button).map(KeyAction::ButtonDown)
+          }
+          SerializableAction::ButtonRelease { button } => {
+            string_to_button(&button).map(KeyAction::ButtonUp)
+          }
+          SerializableAction::Type { text } => {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+              (Some(c), None) => Some(KeyAction::Type(c)),
+              _ => None,
+            }
+          }
+          SerializableAction::GamepadPress { button } => {
+            string_to_gamepad_button(&button).map(KeyAction::GamepadDown)
+          }
+          SerializableAction::GamepadRelease { button } => {
+            string_to_gamepad_button(&button).map(KeyAction::GamepadUp)
+          }
+        };
+        if action.is_none() {
+          skipped += 1;
+        }
+        let at = match (ev.at_us, ev.delta_ms) {
+          (Some(us), _) => Duration::from_micros(us),
+          (None, Some(ms)) => Duration::from_millis(ms),
+          (None, None) => Duration::ZERO,
+        };
+        action.map(|action| TimedEvent { at, action })
+      })
+      .collect();
+    let mut sample = Sample::new(self.name, events);
+    sample.offset_ms = self.offset_ms;
+    sample.tags = self.tags;
+    sample.window_title = self.window_title;
+    sample.started_at = self.started_at;
+    sample.loop_region = self
+      .loop_region_us
+      .map(|(start, end)| (Duration::from_micros(start), Duration::from_micros(end)));
+    sample.playback_remap = self
+      .remap
+      .iter()
+      .filter_map(|(from, to)| Some((string_to_key(from)?, string_to_key(to)?)))
+      .collect();
+    sample.muted_keys = self.muted.iter().filter_map(|k| string_to_key(k)).collect();
+    (sample, skipped)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Instant;
+
+  #[test]
+  fn named_keys_round_trip_through_their_names() {
+    for &(name, key) in NAMED_KEYS {
+      assert_eq!(key_to_string(key).as_deref(), Some(name));
+      assert_eq!(string_to_key(name), Some(key));
+    }
+  }
+
+  /// A fresh directory under the system temp dir for one test's files.
+  fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("auto-note-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn legacy_bare_array_loads_as_version_0() {
+    let dir = scratch_dir("legacy");
+    let path = dir.join("samples.json");
+    fs::write(
+      &path,
+      r#"[{"name": "take", "offset_ms": 25, "events": [
+        {"delta_ms": 0, "mode": "press", "key": "a"},
+        {"delta_ms": 120, "mode": "release", "key": "a"},
+        {"delta_ms": 130, "mode": "press", "key": "Space"}
+      ]}]"#,
+    )
+    .unwrap();
+    let loaded = load_file(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(loaded.version, 0);
+    assert_eq!(loaded.skipped, 0);
+    assert_eq!(loaded.samples.len(), 1);
+    let sample = &loaded.samples[0];
+    assert_eq!(sample.name, "take");
+    assert_eq!(sample.offset_ms, 25);
+    assert_eq!(
+      sample.events,
+      vec![
+        TimedEvent { at: Duration::ZERO, action: KeyAction::Down(Key::Layout('a')) },
+        TimedEvent { at: Duration::from_millis(120), action: KeyAction::Up(Key::Layout('a')) },
+        TimedEvent { at: Duration::from_millis(130), action: KeyAction::Down(Key::Space) },
+      ]
+    );
+  }
+
+  #[test]
+  fn malformed_json_is_a_parse_error() {
+    let dir = scratch_dir("malformed");
+    let path = dir.join("samples.json");
+    fs::write(&path, r#"[{"name": "take", "events": [{"delta_ms": 0, "mode""#).unwrap();
+    let result = load_file(&path);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(result, Err(LoadError::Parse(_))));
+  }
+
+  #[test]
+  fn newer_json_version_is_refused() {
+    let dir = scratch_dir("newer-json");
+    let path = dir.join("samples.json");
+    let data = format!(r#"{{"version": {}, "samples": []}}"#, SAVE_VERSION + 1);
+    fs::write(&path, data).unwrap();
+    let result = load_file(&path);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(result, Err(LoadError::NewerVersion(v)) if v == SAVE_VERSION + 1));
+  }
+
+  #[test]
+  fn newer_binary_version_is_refused() {
+    let dir = scratch_dir("newer-bin");
+    let path = dir.join(format!("samples.{}", BINARY_EXTENSION));
+    // Only the leading version is read before refusing, so the rest needn't parse.
+    let bytes = bincode::serialize(&(SAVE_VERSION + 1, "not a sample list")).unwrap();
+    fs::write(&path, bytes).unwrap();
+    let result = load_file(&path);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(result, Err(LoadError::NewerVersion(v)) if v == SAVE_VERSION + 1));
+  }
+
+  /// 10k events load faster from bincode than from JSON. Timing-dependent, so ignored
+  /// by default; run with `cargo test --release -- --ignored load_speed`.
+  #[test]
+  #[ignore]
+  fn load_speed_binary_beats_json() {
+    let events = (0..10_000u64)
+      .map(|i| {
+        let key = Key::Layout((b'a' + (i / 2 % 26) as u8) as char);
+        let action = match i % 2 {
+          0 => KeyAction::Down(key),
+          _ => KeyAction::Up(key),
+        };
+        TimedEvent { at: Duration::from_millis(i * 7), action }
+      })
+      .collect();
+    let samples = [Sample::new("bench".into(), events)];
+    let dir = scratch_dir("load-speed");
+    let time_load = |ext: &str| {
+      let path = dir.join(format!("samples.{}", ext));
+      save_file(&path, &samples, false).unwrap();
+      let start = Instant::now();
+      let loaded = load_file(&path).unwrap();
+      let elapsed = start.elapsed();
+      assert_eq!(loaded.samples[0].events.len(), 10_000);
+      elapsed
+    };
+    let json = time_load("json");
+    let binary = time_load(BINARY_EXTENSION);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(binary < json, "binary {:?}, json {:?}", binary, json);
+  }
+}
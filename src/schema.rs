@@ -1,14 +1,45 @@
-
-use enigo::Key;
+use enigo::{Key, MouseButton};
 use std::time::Duration;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum KeyAction {
   Down(Key),
   Up(Key),
+  MouseMove { x: f64, y: f64 },
+  ButtonDown(MouseButton),
+  ButtonUp(MouseButton),
+  /// A character sent as text, so the OS produces the glyph whatever the Shift state.
+  Type(char),
+  /// Controller buttons, recorded with the `gamepad` feature. Playback sends the key
+  /// mapped to the button, if any.
+  GamepadDown(GamepadButton),
+  GamepadUp(GamepadButton),
+}
+
+/// Controller button by position, using Xbox names for the face buttons' layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadButton {
+  /// Bottom face button (A on Xbox, Cross on PlayStation).
+  South,
+  East,
+  North,
+  West,
+  LeftBumper,
+  RightBumper,
+  LeftTrigger,
+  RightTrigger,
+  Select,
+  Start,
+  Mode,
+  LeftThumb,
+  RightThumb,
+  DPadUp,
+  DPadDown,
+  DPadLeft,
+  DPadRight,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TimedEvent {
   pub at: Duration,
   pub action: KeyAction,
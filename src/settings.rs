@@ -0,0 +1,125 @@
+//! User preferences kept across launches in `settings.toml` in the config directory.
+
+use crate::macro_play::{GapMode, TimingMode};
+use crate::stats::OutlierMethod;
+use crate::transform::TieOrder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Playback, recording and hotkey preferences. Fields missing from the file keep
+/// their defaults, so older files stay readable.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  /// Hotkeys by rdev key name, e.g. `F9`.
+  pub record_hotkey: String,
+  pub playback_hotkey: String,
+  pub panic_hotkey: String,
+  pub next_sample_hotkey: String,
+  pub prev_sample_hotkey: String,
+  pub playback_offset_ms: i64,
+  pub clamp_offset: bool,
+  pub playback_repeat: u32,
+  /// Random pause between repeats, as `[min_ms, max_ms]`.
+  pub loop_gap_ms: (u64, u64),
+  pub jitter_ms: f64,
+  pub timing_mode: TimingMode,
+  pub min_gap_ms: u64,
+  pub gap_mode: GapMode,
+  pub ignore_autorepeat: bool,
+  /// Repeat window in ms; absent means repeats are dropped for as long as a key is held.
+  pub autorepeat_window_ms: Option<u64>,
+  pub debounce_ms: u64,
+  pub fold_typing: bool,
+  pub auto_stop_idle_ms: Option<u64>,
+  pub save_micros: bool,
+  pub rebase_on_import: bool,
+  pub tie_order: TieOrder,
+  /// Keyboard device node to record keys from; absent records every keyboard.
+  pub input_device: Option<PathBuf>,
+  // Written as a table, so it has to stay after the plain values.
+  pub outliers: OutlierMethod,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      record_hotkey: "F9".into(),
+      playback_hotkey: "F10".into(),
+      panic_hotkey: "Escape".into(),
+      next_sample_hotkey: "F8".into(),
+      prev_sample_hotkey: "F7".into(),
+      playback_offset_ms: 0,
+      clamp_offset: false,
+      playback_repeat: 1,
+      loop_gap_ms: (0, 0),
+      jitter_ms: 0.0,
+      timing_mode: TimingMode::default(),
+      min_gap_ms: 0,
+      gap_mode: GapMode::default(),
+      ignore_autorepeat: false,
+      autorepeat_window_ms: None,
+      debounce_ms: 0,
+      fold_typing: false,
+      auto_stop_idle_ms: None,
+      save_micros: false,
+      rebase_on_import: false,
+      tie_order: TieOrder::default(),
+      input_device: None,
+      outliers: OutlierMethod::default(),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+  Io(io::Error),
+  Parse(toml::de::Error),
+}
+
+impl fmt::Display for SettingsError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SettingsError::Io(e) => write!(f, "{}", e),
+      SettingsError::Parse(e) => write!(f, "unreadable settings file: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<io::Error> for SettingsError {
+  fn from(e: io::Error) -> Self {
+    SettingsError::Io(e)
+  }
+}
+
+impl From<toml::de::Error> for SettingsError {
+  fn from(e: toml::de::Error) -> Self {
+    SettingsError::Parse(e)
+  }
+}
+
+impl Settings {
+  pub fn load(path: &Path) -> Result<Self, SettingsError> {
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+  }
+
+  /// Write to `path`, creating its directory if needed.
+  pub fn save(&self, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let text = toml::to_string_pretty(self).map_err(io::Error::other)?;
+    fs::write(path, text)
+  }
+}
+
+/// `settings.toml` in the per-user config directory, if the OS provides one.
+pub fn default_settings_path() -> Option<PathBuf> {
+  directories::ProjectDirs::from("", "", "auto-note")
+    .map(|dirs| dirs.config_dir().join("settings.toml"))
+}
@@ -1,20 +1,175 @@
-use crate::macro_play;
+use crate::csv;
+use crate::export;
+use crate::input_device;
+use crate::keymap::{self, KeyMap, KeyMapError};
+use crate::logbuf::{self, LogBuffer};
+use crate::macro_play::{self, GapMode, PlaybackOptions, PlaybackReport, TimingMode};
+use crate::persist::{self, LoadError};
 use crate::schema;
 use crate::schema::TimedEvent;
-use enigo;
-use rdev::{Event, EventType, Key};
+use crate::settings::{self, Settings};
+use crate::stats::{self, Alignment, OutlierMethod, SlotStats};
+use crate::text_timeline::{self, ParseError};
+use crate::transform::{self, TieOrder};
+use crate::validate;
+use rdev::{Button, Event, EventType, Key};
 use std::sync::{
-  atomic::{AtomicBool, Ordering},
+  atomic::{AtomicBool, AtomicUsize, Ordering},
   Arc, Mutex,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Mouse moves closer than this (in pixels) to the last recorded one are dropped.
+const MOUSE_MIN_DISTANCE_PX: f64 = 3.0;
+/// Mouse moves arriving sooner than this after the last recorded one are dropped.
+const MOUSE_MIN_INTERVAL: Duration = Duration::from_millis(10);
+/// Number of sample snapshots kept for undo.
+const UNDO_DEPTH: usize = 10;
+/// How often a waiting scheduler checks whether it was stopped.
+const SCHEDULER_POLL: Duration = Duration::from_millis(100);
+/// A `play_at` wait spins instead of sleeping once it is this close to the start.
+const TIMED_START_SPIN: Duration = Duration::from_millis(2);
+/// Pause between a sample's last event and the first one recorded onto its end.
+const APPEND_GAP: Duration = Duration::from_millis(50);
+/// Default time between autosaves of the samples.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// `autorepeat_window_ms` value that drops every repeat for as long as the key is held.
+pub const AUTOREPEAT_WHILE_HELD: u64 = u64::MAX;
+
+/// When the current playback started, its offset in ms, the length of one pass and
+/// the point in the sample it started from.
+pub type PlaybackClock = (Instant, i64, Duration, Duration);
 
 #[derive(Clone)]
 pub struct Sample {
   pub name: String,
   pub events: Vec<TimedEvent>,
+  /// Added to the global playback offset when this sample plays.
+  pub offset_ms: i64,
+  /// Ticked in the UI for multi-sample actions such as merging. Not persisted.
+  pub checked: bool,
+  pub tags: Vec<String>,
+  /// Foreground window title when recording started, if the platform exposes it.
+  pub window_title: Option<String>,
+  /// When recording started, in milliseconds since the Unix epoch.
+  pub started_at: Option<u64>,
+  /// Section to play instead of the whole sample; repeats loop just this part.
+  pub loop_region: Option<(Duration, Duration)>,
+  /// Keys sent in place of the recorded ones when this sample plays.
+  pub playback_remap: HashMap<enigo::Key, enigo::Key>,
+  /// Keys kept in the recording but not sent when this sample plays.
+  pub muted_keys: HashSet<enigo::Key>,
+}
+
+impl Sample {
+  pub fn new(name: String, events: Vec<TimedEvent>) -> Self {
+    Self {
+      name,
+      events,
+      offset_ms: 0,
+      checked: false,
+      tags: Vec::new(),
+      window_title: None,
+      started_at: None,
+      loop_region: None,
+      playback_remap: HashMap::new(),
+      muted_keys: HashSet::new(),
+    }
+  }
+
+  /// Shift every event (and the loop region) earlier so the first event is at t=0,
+  /// keeping their spacing. Returns the shift.
+  pub fn rebase_to_zero(&mut self) -> Duration {
+    let Some(first) = self.events.iter().map(|e| e.at).min() else {
+      return Duration::ZERO;
+    };
+    for ev in &mut self.events {
+      ev.at -= first;
+    }
+    if let Some((start, end)) = &mut self.loop_region {
+      *start = start.saturating_sub(first);
+      *end = end.saturating_sub(first);
+    }
+    first
+  }
+
+  /// Events that `offset_ms` would pull before t=0; they all fire at once at t=0.
+  pub fn events_clipped_by(&self, offset_ms: i64) -> usize {
+    if offset_ms >= 0 {
+      return 0;
+    }
+    let cut = Duration::from_millis(offset_ms.unsigned_abs());
+    self.events.iter().filter(|e| e.at < cut).count()
+  }
+
+  /// Case-insensitive substring match against the name and tags; empty matches all.
+  pub fn matches(&self, query: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    query.is_empty()
+      || self.name.to_lowercase().contains(&query)
+      || self.tags.iter().any(|t| t.to_lowercase().contains(&query))
+  }
+
+  /// Time from playback start until the last event fires.
+  pub fn duration(&self) -> Duration {
+    self.events.iter().map(|e| e.at).max().unwrap_or(Duration::ZERO)
+  }
+
+  /// Average events per second over `duration`, 0 for empty or instantaneous samples.
+  pub fn event_rate(&self) -> f64 {
+    let secs = self.duration().as_secs_f64();
+    if secs > 0.0 {
+      self.events.len() as f64 / secs
+    } else {
+      0.0
+    }
+  }
+}
+
+/// Global hotkeys that can be rebound from the UI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+  Record,
+  Playback,
+  Panic,
+  /// Make the next or previous sample the one `Playback` plays.
+  NextSample,
+  PrevSample,
+}
+
+impl Hotkey {
+  pub const ALL: [Hotkey; 5] = [
+    Hotkey::Record,
+    Hotkey::Playback,
+    Hotkey::Panic,
+    Hotkey::NextSample,
+    Hotkey::PrevSample,
+  ];
+
+  /// The key each hotkey is bound to until the user rebinds it.
+  pub fn default_key(self) -> Key {
+    match self {
+      Hotkey::Record => Key::F9,
+      Hotkey::Playback => Key::F10,
+      Hotkey::Panic => Key::Escape,
+      Hotkey::NextSample => Key::F8,
+      Hotkey::PrevSample => Key::F7,
+    }
+  }
+}
+
+/// How `merge_samples` combines timelines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+  /// Interleave all events by time, as if the samples ran simultaneously.
+  Overlay,
+  /// Play the samples one after another with `gap_ms` between them.
+  Sequential { gap_ms: u64 },
 }
 
 #[derive(Clone)]
@@ -24,11 +179,124 @@ pub struct AppState {
   pub current_events: Arc<Mutex<Vec<TimedEvent>>>,
   pub samples: Arc<Mutex<Vec<Sample>>>,
   pub playback_stop: Arc<AtomicBool>,
+  pub playback_paused: Arc<AtomicBool>,
+  /// Timing accuracy of the last finished playback.
+  pub playback_report: Arc<Mutex<Option<PlaybackReport>>>,
+  pub scheduler_stop: Arc<AtomicBool>,
+  pub scheduler_handle: Arc<Mutex<Option<std::thread::JoinHandle<()>>>>,
+  /// Sample the scheduler is replaying, if it is running.
+  pub scheduled_sample: Arc<Mutex<Option<usize>>>,
   pub playback_handle: Arc<Mutex<Option<std::thread::JoinHandle<()>>>>,
   pub playback_offset_ms: Arc<Mutex<i64>>,
+  pub playback_repeat: Arc<Mutex<u32>>,
+  /// Random pause range in ms between repeats; `(0, 0)` plays them back to back.
+  pub loop_gap_ms: Arc<Mutex<(u64, u64)>>,
+  /// Limit negative offsets so the earliest event lands at t=0 instead of clipping.
+  pub clamp_offset: Arc<AtomicBool>,
+  pub jitter_ms: Arc<Mutex<f64>>,
+  pub timing_mode: Arc<Mutex<TimingMode>>,
+  /// Play without sending anything: only the log and playhead show the run.
+  pub dry_run: Arc<AtomicBool>,
+  /// Minimum ms between sent key and button events during playback; 0 disables it.
+  pub min_gap_ms: Arc<Mutex<u64>>,
+  pub gap_mode: Arc<Mutex<GapMode>>,
+  /// Playback offset in ms per key, on top of the global and per-sample offsets.
+  pub per_key_offset: Arc<Mutex<HashMap<enigo::Key, i64>>>,
+  /// Key played back for each controller button.
+  pub gamepad_keys: Arc<Mutex<HashMap<schema::GamepadButton, enigo::Key>>>,
+  /// Order of presses and releases that share a timestamp during playback.
+  pub tie_order: Arc<Mutex<TieOrder>>,
+  /// Modifiers held down for the whole of each playback.
+  pub wrap_modifiers: Arc<Mutex<Vec<enigo::Key>>>,
+  /// MIDI note per key for "Export MIDI"; unmapped keys are left out of the file.
+  pub midi_notes: Arc<Mutex<HashMap<enigo::Key, u8>>>,
   pub playing: Arc<AtomicBool>,
+  pub playback_clock: Arc<Mutex<Option<PlaybackClock>>>,
+  /// Start point for the next `start_playback`, set by `playback_sample_from`.
+  playback_from: Arc<Mutex<Option<Duration>>>,
+  /// Jitter seed for the next `start_playback`, set by `replay_with_seed`.
+  playback_seed: Arc<Mutex<Option<u64>>>,
   pub tracked_keys: Arc<Mutex<Vec<enigo::Key>>>,
+  /// Translates recorded rdev keys for the user's keyboard layout.
+  pub key_map: Arc<Mutex<KeyMap>>,
   pub key_states: Arc<Mutex<HashMap<enigo::Key, bool>>>,
+  pub mouse_pos: Arc<Mutex<Option<(f64, f64)>>>,
+  pub last_mouse_move: Arc<Mutex<Option<(f64, f64, Duration)>>>,
+  pub record_hotkey: Arc<Mutex<Key>>,
+  pub playback_hotkey: Arc<Mutex<Key>>,
+  pub panic_hotkey: Arc<Mutex<Key>>,
+  pub next_sample_hotkey: Arc<Mutex<Key>>,
+  pub prev_sample_hotkey: Arc<Mutex<Key>>,
+  /// Hotkey waiting to be bound to the next key press, if any.
+  pub rebinding: Arc<Mutex<Option<Hotkey>>>,
+  pub log_lines: LogBuffer,
+  /// Sample targeted by per-sample tools such as step-through playback.
+  pub selected_sample: Arc<Mutex<Option<usize>>>,
+  /// Sample the playback hotkey plays; a new recording becomes active.
+  pub active_sample: Arc<Mutex<usize>>,
+  pub step_cursor: Arc<Mutex<usize>>,
+  pub outlier_method: Arc<Mutex<OutlierMethod>>,
+  /// Snapshots of `samples` taken before each mutation, newest last.
+  pub undo_stack: Arc<Mutex<Vec<Vec<Sample>>>>,
+  /// When set, keys are recorded only from this keyboard device node; see
+  /// `set_input_device`.
+  input_device: Arc<Mutex<Option<PathBuf>>>,
+  /// Bumped on every device change, so a listener for an older selection knows to quit
+  /// even if the same device was picked again.
+  input_device_epoch: Arc<AtomicUsize>,
+  /// When set, only these keys are recorded.
+  pub allowed_keys: Arc<Mutex<Option<Vec<enigo::Key>>>>,
+  /// Where `autosave` writes the samples; `None` disables autosave.
+  pub autosave_path: Arc<Mutex<Option<PathBuf>>>,
+  pub autosave_interval: Arc<Mutex<Duration>>,
+  /// Files a newer version wrote; saves and autosaves refuse to overwrite them.
+  newer_files: Arc<Mutex<HashSet<PathBuf>>>,
+  /// Autosave path whose existing file has already been checked for a newer version.
+  autosave_checked: Arc<Mutex<Option<PathBuf>>>,
+  /// Shift loaded and imported samples so their first event is at t=0.
+  pub rebase_on_import: Arc<AtomicBool>,
+  /// Where `load_settings` and `save_settings` keep preferences; `None` disables them.
+  pub settings_path: Arc<Mutex<Option<PathBuf>>>,
+  /// Save timestamps in microseconds rather than milliseconds.
+  pub save_micros: Arc<AtomicBool>,
+  /// Drop repeated key presses that arrive while the key is already held.
+  pub ignore_autorepeat: Arc<AtomicBool>,
+  /// Store characters pressed with Shift as typed text when recording stops.
+  pub fold_typing: Arc<AtomicBool>,
+  /// A held key's Down within this many ms of its last recorded Down counts as a repeat.
+  pub autorepeat_window_ms: Arc<Mutex<u64>>,
+  /// When each held key's last Down was recorded.
+  last_downs: Arc<Mutex<HashMap<enigo::Key, Instant>>>,
+  /// Key presses in the current (or last) recording that the key map couldn't convert.
+  pub unmapped_count: Arc<AtomicUsize>,
+  /// Software de-bounce for keyboards that chatter: a key event reversing that key's
+  /// previous event within this many ms drops both. 0 disables it; it's off by default
+  /// because it also swallows genuine double taps that fast.
+  pub debounce_ms: Arc<Mutex<u64>>,
+  /// Foreground window title captured by `start_recording`.
+  pub recording_window: Arc<Mutex<Option<String>>>,
+  /// Wall-clock start of the current recording, in ms since the Unix epoch.
+  pub recording_started_at: Arc<Mutex<Option<u64>>>,
+  /// Sample the current recording is appended to instead of becoming a new sample.
+  pub append_target: Arc<Mutex<Option<usize>>>,
+  /// Sample waiting for a wall-clock start set by `play_at`.
+  pub timed_start: Arc<Mutex<Option<(usize, SystemTime)>>>,
+  /// When the text queued by `send_test_text` will be typed.
+  pub test_send_at: Arc<Mutex<Option<Instant>>>,
+  /// Stop recording after this long without key or button activity; `None` disables it.
+  pub auto_stop_idle_ms: Arc<Mutex<Option<u64>>>,
+  /// Last key or button activity while recording.
+  last_activity: Arc<Mutex<Option<Instant>>>,
+  /// Why the global input listener failed to start, if it did.
+  pub listener_error: Arc<Mutex<Option<String>>>,
+  /// Playback held back until the user confirms a window title mismatch.
+  pub pending_playback: Arc<Mutex<Option<Sample>>>,
+}
+
+impl Default for AppState {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl AppState {
@@ -39,107 +307,1444 @@ impl AppState {
       current_events: Arc::new(Mutex::new(Vec::new())),
       samples: Arc::new(Mutex::new(Vec::new())),
       playback_stop: Arc::new(AtomicBool::new(false)),
+      playback_paused: Arc::new(AtomicBool::new(false)),
+      playback_report: Arc::new(Mutex::new(None)),
+      scheduler_stop: Arc::new(AtomicBool::new(false)),
+      scheduler_handle: Arc::new(Mutex::new(None)),
+      scheduled_sample: Arc::new(Mutex::new(None)),
       playback_handle: Arc::new(Mutex::new(None)),
       playback_offset_ms: Arc::new(Mutex::new(0)),
+      playback_repeat: Arc::new(Mutex::new(1)),
+      loop_gap_ms: Arc::new(Mutex::new((0, 0))),
+      clamp_offset: Arc::new(AtomicBool::new(false)),
+      jitter_ms: Arc::new(Mutex::new(0.0)),
+      timing_mode: Arc::new(Mutex::new(TimingMode::default())),
+      dry_run: Arc::new(AtomicBool::new(false)),
+      min_gap_ms: Arc::new(Mutex::new(0)),
+      gap_mode: Arc::new(Mutex::new(GapMode::default())),
+      per_key_offset: Arc::new(Mutex::new(HashMap::new())),
+      gamepad_keys: Arc::new(Mutex::new(HashMap::new())),
+      tie_order: Arc::new(Mutex::new(TieOrder::default())),
+      wrap_modifiers: Arc::new(Mutex::new(Vec::new())),
+      midi_notes: Arc::new(Mutex::new(HashMap::new())),
       playing: Arc::new(AtomicBool::new(false)),
+      playback_clock: Arc::new(Mutex::new(None)),
+      playback_from: Arc::new(Mutex::new(None)),
+      playback_seed: Arc::new(Mutex::new(None)),
       tracked_keys: Arc::new(Mutex::new(Vec::new())),
+      key_map: Arc::new(Mutex::new(KeyMap::default())),
       key_states: Arc::new(Mutex::new(HashMap::new())),
+      mouse_pos: Arc::new(Mutex::new(None)),
+      last_mouse_move: Arc::new(Mutex::new(None)),
+      record_hotkey: Arc::new(Mutex::new(Hotkey::Record.default_key())),
+      playback_hotkey: Arc::new(Mutex::new(Hotkey::Playback.default_key())),
+      panic_hotkey: Arc::new(Mutex::new(Hotkey::Panic.default_key())),
+      next_sample_hotkey: Arc::new(Mutex::new(Hotkey::NextSample.default_key())),
+      prev_sample_hotkey: Arc::new(Mutex::new(Hotkey::PrevSample.default_key())),
+      rebinding: Arc::new(Mutex::new(None)),
+      log_lines: Arc::new(Mutex::new(VecDeque::new())),
+      selected_sample: Arc::new(Mutex::new(None)),
+      active_sample: Arc::new(Mutex::new(0)),
+      step_cursor: Arc::new(Mutex::new(0)),
+      outlier_method: Arc::new(Mutex::new(OutlierMethod::default())),
+      undo_stack: Arc::new(Mutex::new(Vec::new())),
+      input_device: Arc::new(Mutex::new(None)),
+      input_device_epoch: Arc::new(AtomicUsize::new(0)),
+      allowed_keys: Arc::new(Mutex::new(None)),
+      autosave_path: Arc::new(Mutex::new(default_autosave_path())),
+      autosave_interval: Arc::new(Mutex::new(AUTOSAVE_INTERVAL)),
+      newer_files: Arc::new(Mutex::new(HashSet::new())),
+      autosave_checked: Arc::new(Mutex::new(None)),
+      rebase_on_import: Arc::new(AtomicBool::new(false)),
+      settings_path: Arc::new(Mutex::new(settings::default_settings_path())),
+      save_micros: Arc::new(AtomicBool::new(false)),
+      ignore_autorepeat: Arc::new(AtomicBool::new(false)),
+      fold_typing: Arc::new(AtomicBool::new(false)),
+      autorepeat_window_ms: Arc::new(Mutex::new(AUTOREPEAT_WHILE_HELD)),
+      last_downs: Arc::new(Mutex::new(HashMap::new())),
+      debounce_ms: Arc::new(Mutex::new(0)),
+      unmapped_count: Arc::new(AtomicUsize::new(0)),
+      recording_window: Arc::new(Mutex::new(None)),
+      recording_started_at: Arc::new(Mutex::new(None)),
+      append_target: Arc::new(Mutex::new(None)),
+      timed_start: Arc::new(Mutex::new(None)),
+      test_send_at: Arc::new(Mutex::new(None)),
+      auto_stop_idle_ms: Arc::new(Mutex::new(None)),
+      last_activity: Arc::new(Mutex::new(None)),
+      listener_error: Arc::new(Mutex::new(None)),
+      pending_playback: Arc::new(Mutex::new(None)),
     }
   }
 
+  /// Append a line to the in-app log panel.
+  pub fn log(&self, msg: String) {
+    logbuf::push_line(&self.log_lines, msg);
+  }
+
   pub fn spawn_global_listener(&self) {
     let state = self.clone();
     thread::spawn(move || {
-      if let Err(error) = rdev::listen(move |event| handle_event(&state, event)) {
-        eprintln!("Listener error: {:?}", error);
+      let log_state = state.clone();
+      let listen = rdev::listen(move |event| {
+        // With a device chosen, its own listener records keys; rdev still runs hotkeys.
+        let is_key = matches!(event.event_type, EventType::KeyPress(_) | EventType::KeyRelease(_));
+        if is_key && state.input_device.lock().unwrap().is_some() {
+          handle_hotkey(&state, &event);
+        } else {
+          handle_event(&state, event);
+        }
+      });
+      if let Err(error) = listen {
+        log_state.log(format!("Listener error: {:?}", error));
+        *log_state.listener_error.lock().unwrap() = Some(listener_error_message(&error));
       }
     });
   }
 
+  /// Keyboard device node keys are recorded from; `None` records every keyboard.
+  pub fn input_device(&self) -> Option<PathBuf> {
+    self.input_device.lock().unwrap().clone()
+  }
+
+  /// Record keys only from the device node at `path`, or from every keyboard with
+  /// `None`. Takes effect immediately, including mid-recording.
+  pub fn set_input_device(&self, path: Option<PathBuf>) {
+    let mut current = self.input_device.lock().unwrap();
+    if *current == path {
+      return;
+    }
+    *current = path.clone();
+    let epoch = self.input_device_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+    drop(current);
+    match path {
+      Some(path) => input_device::spawn_listener(self.clone(), path, epoch),
+      None => self.log("Recording keys from all keyboards".into()),
+    }
+  }
+
+  /// Which `set_input_device` call the current selection came from.
+  pub fn input_device_epoch(&self) -> usize {
+    self.input_device_epoch.load(Ordering::SeqCst)
+  }
+
+  /// Clear the device selection if it is still the one from `epoch`, e.g. once that
+  /// device is gone.
+  pub fn forget_input_device(&self, epoch: usize) {
+    let mut current = self.input_device.lock().unwrap();
+    if self.input_device_epoch() == epoch && current.is_some() {
+      *current = None;
+      self.input_device_epoch.fetch_add(1, Ordering::SeqCst);
+      drop(current);
+      self.log("Recording keys from all keyboards".into());
+    }
+  }
+
+  /// Record a controller button press or release, if recording.
+  pub fn record_gamepad(&self, action: schema::KeyAction) {
+    if !self.recording.load(Ordering::SeqCst) {
+      return;
+    }
+    let Some(start_at) = *self.start.lock().unwrap() else {
+      return;
+    };
+    *self.last_activity.lock().unwrap() = Some(Instant::now());
+    push_event(action, start_at, &self.current_events);
+  }
+
   pub fn start_recording(&self) {
     self.current_events.lock().unwrap().clear();
+    *self.last_mouse_move.lock().unwrap() = None;
+    self.unmapped_count.store(0, Ordering::SeqCst);
+    *self.recording_window.lock().unwrap() = foreground_window_title();
+    *self.recording_started_at.lock().unwrap() = unix_millis();
     *self.start.lock().unwrap() = Some(Instant::now());
+    *self.last_activity.lock().unwrap() = Some(Instant::now());
     self.recording.store(true, Ordering::SeqCst);
   }
 
+  /// Stop recording if `auto_stop_idle_ms` has passed without activity. Meant to be
+  /// polled; returns whether it stopped.
+  pub fn stop_if_idle(&self) -> bool {
+    if !self.recording.load(Ordering::SeqCst) {
+      return false;
+    }
+    let Some(idle_ms) = *self.auto_stop_idle_ms.lock().unwrap() else {
+      return false;
+    };
+    let idle = self
+      .last_activity
+      .lock()
+      .unwrap()
+      .is_some_and(|last| last.elapsed() >= Duration::from_millis(idle_ms));
+    if idle {
+      self.stop_recording();
+      self.log(format!("Recording stopped after {} ms without input", idle_ms));
+    }
+    idle
+  }
+
   pub fn stop_recording(&self) {
     let was_recording = self.recording.swap(false, Ordering::SeqCst);
     if !was_recording {
       return;
     }
-    let snapshot = self.current_events.lock().unwrap().clone();
+    let unmapped = self.unmapped_count.load(Ordering::SeqCst);
+    if unmapped > 0 {
+      self.log(format!("{} keys not captured (unmapped)", unmapped));
+    }
+    let mut snapshot = self.current_events.lock().unwrap().clone();
+    if self.fold_typing.load(Ordering::SeqCst) {
+      snapshot = transform::fold_shifted_typing(&snapshot);
+    }
+    let target = self.append_target.lock().unwrap().take();
     if !snapshot.is_empty() {
       let mut samples = self.samples.lock().unwrap();
+      self.push_undo(&samples);
+      if let Some(sample) = target.and_then(|idx| samples.get_mut(idx)) {
+        // Rebase on the first new event so it lands a fixed gap after the last old one,
+        // never on the same instant.
+        let start = sample.duration() + APPEND_GAP;
+        let first = snapshot[0].at;
+        sample.events.extend(snapshot.iter().map(|e| TimedEvent {
+          at: start + (e.at - first),
+          action: e.action,
+        }));
+        self.log(format!(
+          "Appended {} events to {}",
+          snapshot.len(),
+          sample.name
+        ));
+        return;
+      }
       let name = format!("Sample {}", samples.len() + 1);
-      samples.push(Sample { name, events: snapshot });
+      let mut sample = Sample::new(name, snapshot);
+      sample.window_title = self.recording_window.lock().unwrap().clone();
+      sample.started_at = *self.recording_started_at.lock().unwrap();
+      samples.push(sample);
+      *self.active_sample.lock().unwrap() = samples.len() - 1;
     }
   }
 
+  /// Start recording onto the end of sample `idx`.
+  pub fn continue_recording(&self, idx: usize) {
+    self.start_recording();
+    *self.append_target.lock().unwrap() = Some(idx);
+  }
+
   pub fn playback_latest(&self) {
     let samples = self.samples.lock().unwrap();
     if let Some(last) = samples.last() {
-      self.playback_sample(&last.events);
+      self.playback_sample(last);
     } else {
-      println!("No samples to play.");
+      self.log("No samples to play.".into());
+    }
+  }
+
+  /// Index of the active sample, kept in range as samples are removed; `None` if
+  /// there are no samples.
+  pub fn active_index(&self) -> Option<usize> {
+    let len = self.samples.lock().unwrap().len();
+    let active = *self.active_sample.lock().unwrap();
+    len.checked_sub(1).map(|last| active.min(last))
+  }
+
+  /// Play the active sample.
+  pub fn playback_active(&self) {
+    let sample = self
+      .active_index()
+      .and_then(|idx| self.samples.lock().unwrap().get(idx).cloned());
+    match sample {
+      Some(sample) => self.playback_sample(&sample),
+      None => self.log("No samples to play.".into()),
+    }
+  }
+
+  /// Move the active sample `step` places, wrapping around at either end.
+  pub fn cycle_active_sample(&self, step: isize) {
+    let samples = self.samples.lock().unwrap();
+    if samples.is_empty() {
+      return;
+    }
+    let len = samples.len() as isize;
+    let mut active = self.active_sample.lock().unwrap();
+    let current = (*active).min(samples.len() - 1) as isize;
+    *active = (current + step).rem_euclid(len) as usize;
+    let name = &samples[*active].name;
+    self.log(format!("Active sample: #{} {}", *active + 1, name));
+  }
+
+  /// Global plus per-sample offset, before any clamping.
+  pub fn combined_offset_ms(&self, sample: &Sample) -> i64 {
+    *self.playback_offset_ms.lock().unwrap() + sample.offset_ms
+  }
+
+  /// Current playback settings applied to `sample`.
+  pub fn playback_options(&self, sample: &Sample) -> PlaybackOptions {
+    let mut offset_ms = self.combined_offset_ms(sample);
+    if self.clamp_offset.load(Ordering::SeqCst)
+      && let Some(first) = sample.events.iter().map(|e| e.at).min()
+    {
+      offset_ms = offset_ms.max(-(first.as_millis() as i64));
+    }
+    PlaybackOptions {
+      offset_ms,
+      repeat: *self.playback_repeat.lock().unwrap(),
+      loop_gap_ms: *self.loop_gap_ms.lock().unwrap(),
+      jitter_ms: *self.jitter_ms.lock().unwrap(),
+      seed: fresh_seed(),
+      timing: *self.timing_mode.lock().unwrap(),
+      dry_run: self.dry_run.load(Ordering::SeqCst),
+      min_gap_ms: *self.min_gap_ms.lock().unwrap(),
+      gap_mode: *self.gap_mode.lock().unwrap(),
+      key_offsets_ms: self.per_key_offset.lock().unwrap().clone(),
+      key_remap: sample.playback_remap.clone(),
+      muted_keys: sample.muted_keys.clone(),
+      gamepad_keys: self.gamepad_keys.lock().unwrap().clone(),
+      wrap_modifiers: self.wrap_modifiers.lock().unwrap().clone(),
+      tie_order: *self.tie_order.lock().unwrap(),
+    }
+  }
+
+  /// Play `sample`, unless it was recorded in a different foreground window; then it
+  /// waits in `pending_playback` for the user to confirm.
+  pub fn playback_sample(&self, sample: &Sample) {
+    if let Some((start, end)) = sample.loop_region {
+      let mut section = sample.clone();
+      section.events = transform::region(&sample.events, start, end);
+      section.loop_region = None;
+      *self.playback_from.lock().unwrap() = Some(start);
+      return self.playback_sample(&section);
+    }
+    if let (Some(expected), Some(current)) = (&sample.window_title, foreground_window_title())
+      && *expected != current
+    {
+      self.log(format!(
+        "{} was recorded in \"{}\" but \"{}\" is in front; waiting for confirmation",
+        sample.name, expected, current
+      ));
+      *self.pending_playback.lock().unwrap() = Some(sample.clone());
+      return;
     }
+    self.start_playback(sample);
+  }
+
+  /// Play `sample` from `start_ms` on, first pressing whatever was held at that point.
+  /// The sample's loop region is ignored.
+  pub fn playback_sample_from(&self, sample: &Sample, start_ms: u64) {
+    let start = Duration::from_millis(start_ms);
+    let mut partial = sample.clone();
+    partial.events = transform::start_from(&sample.events, start);
+    partial.loop_region = None;
+    *self.playback_from.lock().unwrap() = Some(start);
+    self.playback_sample(&partial);
   }
 
-  pub fn playback_sample(&self, sample: &[TimedEvent]) {
-    log_recorded_events(&sample);
-    if sample.is_empty() {
-      println!("No events recorded; nothing to play back.");
+  /// Play sample `sample_idx` with the jitter of an earlier run, from the seed in its
+  /// `playback_report`. Other settings are read fresh, so they must match too for an
+  /// identical run.
+  pub fn replay_with_seed(&self, sample_idx: usize, seed: u64) {
+    let sample = self.samples.lock().unwrap().get(sample_idx).cloned();
+    let Some(sample) = sample else {
+      return;
+    };
+    self.log(format!("Replaying #{} with seed {}", sample_idx + 1, seed));
+    *self.playback_seed.lock().unwrap() = Some(seed);
+    self.playback_sample(&sample);
+  }
+
+  /// Play the sample held back by a window mismatch, if any.
+  pub fn confirm_pending_playback(&self) {
+    if let Some(sample) = self.pending_playback.lock().unwrap().take() {
+      self.start_playback(&sample);
+    }
+  }
+
+  pub fn cancel_pending_playback(&self) {
+    self.pending_playback.lock().unwrap().take();
+    self.playback_from.lock().unwrap().take();
+    self.playback_seed.lock().unwrap().take();
+  }
+
+  fn start_playback(&self, sample: &Sample) {
+    let events = &sample.events;
+    log_recorded_events(self, events);
+    if events.is_empty() {
+      self.log("No events recorded; nothing to play back.".into());
       return;
     }
     self.stop_playback(); // stop any ongoing playback before starting new
     self.playback_stop.store(false, Ordering::SeqCst);
+    self.playback_paused.store(false, Ordering::SeqCst);
     self.playing.store(true, Ordering::SeqCst);
-    let offset_ms = *self.playback_offset_ms.lock().unwrap();
-    let max_at = sample
+    let mut opts = self.playback_options(sample);
+    if let Some(seed) = self.playback_seed.lock().unwrap().take() {
+      opts.seed = seed;
+    }
+    let clipped = sample.events_clipped_by(opts.offset_ms);
+    if clipped > 0 {
+      self.log(format!("Offset {} ms clips {} events to t=0", opts.offset_ms, clipped));
+    }
+    let max_at = events
       .iter()
-      .map(|e| apply_offset(e.at, offset_ms))
+      .map(|e| apply_offset(e.at, opts.offset_ms))
       .max()
       .unwrap_or(Duration::from_millis(0));
+    let from = self
+      .playback_from
+      .lock()
+      .unwrap()
+      .take()
+      .unwrap_or_default();
+    *self.playback_clock.lock().unwrap() = Some((Instant::now(), opts.offset_ms, max_at, from));
     let stop_flag = self.playback_stop.clone();
-    let handle = macro_play::play_timeline_async(sample.to_vec(), stop_flag, offset_ms);
-    *self.playback_handle.lock().unwrap() = Some(handle);
-    // Schedule a watcher thread to auto-clear the handle after expected duration.
-    let handle_ref = self.playback_handle.clone();
+    let player = macro_play::play_timeline_async(
+      events.clone(),
+      stop_flag,
+      self.playback_paused.clone(),
+      opts,
+      self.log_lines.clone(),
+      self.playback_report.clone(),
+    );
+    // The player clears `playing` itself when it ends; only stop_playback joins it, so
+    // the handle has exactly one owner.
     let playing_flag = self.playing.clone();
+    let handle = thread::spawn(move || {
+      let _ = player.join();
+      playing_flag.store(false, Ordering::SeqCst);
+    });
+    *self.playback_handle.lock().unwrap() = Some(handle);
+  }
+
+  /// Position within the playing sample's timeline, wrapping on each repeat.
+  pub fn playback_position(&self) -> Option<Duration> {
+    if !self.playing.load(Ordering::SeqCst) {
+      return None;
+    }
+    let (started, offset_ms, pass, from) = (*self.playback_clock.lock().unwrap())?;
+    let mut elapsed = started.elapsed();
+    if !pass.is_zero() {
+      elapsed = Duration::from_secs_f64(elapsed.as_secs_f64() % pass.as_secs_f64());
+    }
+    Some(from + apply_offset(elapsed, -offset_ms))
+  }
+
+  /// Time into the current pass and the length of one pass, for a progress display;
+  /// `None` when not playing.
+  pub fn playback_progress(&self) -> Option<(Duration, Duration)> {
+    if !self.playing.load(Ordering::SeqCst) {
+      return None;
+    }
+    let (started, _, pass, _) = (*self.playback_clock.lock().unwrap())?;
+    let mut elapsed = started.elapsed();
+    if !pass.is_zero() {
+      elapsed = Duration::from_secs_f64(elapsed.as_secs_f64() % pass.as_secs_f64());
+    }
+    Some((elapsed.min(pass), pass))
+  }
+
+  /// Play sample `sample_idx` every `interval` until `stop_scheduler`. A tick is skipped
+  /// while a previous playback is still running.
+  pub fn start_scheduler(&self, sample_idx: usize, interval: Duration) {
+    self.stop_scheduler();
+    self.scheduler_stop.store(false, Ordering::SeqCst);
+    *self.scheduled_sample.lock().unwrap() = Some(sample_idx);
+    self.log(format!("Scheduler: sample #{} every {:?}", sample_idx + 1, interval));
+    let state = self.clone();
+    let handle = thread::spawn(move || {
+      while !state.scheduler_stop.load(Ordering::SeqCst) {
+        if !state.playing.load(Ordering::SeqCst) {
+          let sample = state.samples.lock().unwrap().get(sample_idx).cloned();
+          let Some(sample) = sample else {
+            state.log(format!("Scheduler: sample #{} is gone, stopping", sample_idx + 1));
+            break;
+          };
+          state.playback_sample(&sample);
+        }
+        let next = Instant::now() + interval;
+        while Instant::now() < next && !state.scheduler_stop.load(Ordering::SeqCst) {
+          thread::sleep(SCHEDULER_POLL.min(next - Instant::now()));
+        }
+      }
+      *state.scheduled_sample.lock().unwrap() = None;
+    });
+    *self.scheduler_handle.lock().unwrap() = Some(handle);
+  }
+
+  /// Play sample `sample_idx` when the system clock reaches `when`, e.g. to start the
+  /// same macro on several machines at once. The wait is converted to a monotonic
+  /// delay now, so later clock adjustments don't move it. Machines are only as in sync
+  /// as their clocks: NTP typically keeps them within a few ms of each other, and
+  /// sub-millisecond agreement is not guaranteed. A new call replaces a pending one.
+  pub fn play_at(&self, sample_idx: usize, when: SystemTime) {
+    let delay = when.duration_since(SystemTime::now()).unwrap_or_default();
+    let deadline = Instant::now() + delay;
+    *self.timed_start.lock().unwrap() = Some((sample_idx, when));
+    self.log(format!("Sample #{} starts in {:.1} s", sample_idx + 1, delay.as_secs_f64()));
+    let state = self.clone();
     thread::spawn(move || {
-      thread::sleep(max_at + Duration::from_millis(300));
-      if let Some(joined) = handle_ref.lock().unwrap().take() {
-        let _ = joined.join();
+      let still_pending = || *state.timed_start.lock().unwrap() == Some((sample_idx, when));
+      loop {
+        if !still_pending() {
+          return;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+          break;
+        }
+        let remaining = deadline - now;
+        // Sleep in short steps so a cancel is noticed, then spin the last 2 ms.
+        if remaining > TIMED_START_SPIN {
+          thread::sleep((remaining - TIMED_START_SPIN).min(SCHEDULER_POLL));
+        } else {
+          std::hint::spin_loop();
+        }
       }
-      playing_flag.store(false, Ordering::SeqCst);
+      state.timed_start.lock().unwrap().take();
+      let sample = state.samples.lock().unwrap().get(sample_idx).cloned();
+      match sample {
+        Some(sample) => state.playback_sample(&sample),
+        None => state.log(format!("Timed start: sample #{} is gone", sample_idx + 1)),
+      }
+    });
+  }
+
+  /// Type `text` into whatever window has focus after `delay`, to check that input can
+  /// be sent at all (permissions, keyboard layout) before blaming a recording. A new
+  /// call replaces a pending one.
+  pub fn send_test_text(&self, text: String, delay: Duration) {
+    let deadline = Instant::now() + delay;
+    *self.test_send_at.lock().unwrap() = Some(deadline);
+    self.log(format!(
+      "Typing test text in {:.1} s; focus the target window",
+      delay.as_secs_f64()
+    ));
+    let state = self.clone();
+    thread::spawn(move || {
+      while Instant::now() < deadline {
+        thread::sleep(SCHEDULER_POLL.min(deadline.saturating_duration_since(Instant::now())));
+      }
+      {
+        let mut pending = state.test_send_at.lock().unwrap();
+        if *pending != Some(deadline) {
+          return;
+        }
+        pending.take();
+      }
+      if state.playing.load(Ordering::SeqCst) {
+        state.log("Test text skipped: playback is running".into());
+        return;
+      }
+      macro_play::type_text(&text);
+      state.log(format!("Typed test text {:?}", text));
     });
   }
 
-  pub fn merge_samples(&self) {
+  /// Drop a start scheduled by `play_at`.
+  pub fn cancel_play_at(&self) {
+    if self.timed_start.lock().unwrap().take().is_some() {
+      self.log("Timed start cancelled".into());
+    }
+  }
+
+  /// Stop the scheduler and wait for its thread so a new one can't overlap it.
+  pub fn stop_scheduler(&self) {
+    self.scheduler_stop.store(true, Ordering::SeqCst);
+    if let Some(handle) = self.scheduler_handle.lock().unwrap().take() {
+      let _ = handle.join();
+      self.log("Scheduler stopped".into());
+    }
+  }
+
+  pub fn select_sample(&self, idx: Option<usize>) {
+    *self.selected_sample.lock().unwrap() = idx;
+    self.reset_step();
+  }
+
+  pub fn reset_step(&self) {
+    *self.step_cursor.lock().unwrap() = 0;
+  }
+
+  /// Send the event under the step cursor of the selected sample and advance the cursor.
+  /// Returns false once there is nothing left to send.
+  pub fn play_next_step(&self) -> bool {
+    // The running playback holds the input sender until it ends.
+    if self.playing.load(Ordering::SeqCst) {
+      self.log("Step: wait for playback to finish".into());
+      return false;
+    }
+    let Some(idx) = *self.selected_sample.lock().unwrap() else {
+      self.log("Step: no sample selected".into());
+      return false;
+    };
+    let action = {
+      let samples = self.samples.lock().unwrap();
+      let cursor = *self.step_cursor.lock().unwrap();
+      samples.get(idx).and_then(|s| s.events.get(cursor)).map(|e| e.action)
+    };
+    let Some(action) = action else {
+      self.log("Step: end of sample".into());
+      return false;
+    };
+    let mut cursor = self.step_cursor.lock().unwrap();
+    self.log(format!("Step #{}: {}", *cursor, action_label(&action)));
+    macro_play::send_action(&mut macro_play::shared_enigo(), action);
+    *cursor += 1;
+    true
+  }
+
+  /// Merge the samples at `indices` (in list order) into a new sample.
+  pub fn merge_samples(&self, indices: &[usize], strategy: MergeStrategy) {
     let samples = self.samples.lock().unwrap();
-    if samples.is_empty() {
-      println!("No samples to merge.");
+    let chosen: Vec<&Sample> = indices.iter().filter_map(|&i| samples.get(i)).collect();
+    if chosen.is_empty() {
+      self.log("No samples to merge.".into());
       return;
     }
-    let mut merged: Vec<TimedEvent> = samples.iter().flat_map(|s| s.events.clone()).collect();
-    merged.sort_by_key(|e| e.at);
+    let merged = merge_timelines(&chosen, strategy);
     drop(samples);
 
     if merged.is_empty() {
-      println!("Merged result is empty.");
+      self.log("Merged result is empty.".into());
       return;
     }
     let mut samples = self.samples.lock().unwrap();
+    self.push_undo(&samples);
     let name = format!("Merged {}", samples.len() + 1);
-    println!("Merged samples into one timeline with {} events.", merged.len());
-    samples.push(Sample { name, events: merged });
+    self.log(format!("Merged samples into one timeline with {} events.", merged.len()));
+    samples.push(Sample::new(name, merged));
+  }
+
+  /// Play the samples at `indices` layered on top of each other as one timeline, so the
+  /// tracks stay in sync. Each track keeps its own offset, key remap and muted keys;
+  /// nothing is added to the list.
+  pub fn play_tracks(&self, indices: &[usize]) {
+    let tracks: Vec<Sample> = {
+      let samples = self.samples.lock().unwrap();
+      indices.iter().filter_map(|&i| samples.get(i).cloned()).collect()
+    };
+    if tracks.is_empty() {
+      self.log("No tracks to play.".into());
+      return;
+    }
+    let shifted: Vec<Sample> = tracks
+      .iter()
+      .map(|track| {
+        let events = track
+          .events
+          .iter()
+          .map(|e| TimedEvent {
+            at: apply_offset(e.at, track.offset_ms),
+            action: e.action,
+          })
+          .collect();
+        let events = macro_play::drop_keys(events, |k| track.muted_keys.contains(k));
+        let events = macro_play::remap_keys(events, &track.playback_remap);
+        Sample::new(track.name.clone(), events)
+      })
+      .collect();
+    let refs: Vec<&Sample> = shifted.iter().collect();
+    let names: Vec<&str> = tracks.iter().map(|t| t.name.as_str()).collect();
+    let mut layered = Sample::new(
+      names.join(" | "),
+      merge_timelines(&refs, MergeStrategy::Overlay),
+    );
+    layered.window_title = tracks.iter().find_map(|t| t.window_title.clone());
+    self.playback_sample(&layered);
+  }
+
+  /// Append a new sample that plays `first`, waits `gap_ms`, then plays `second`.
+  pub fn concat_samples(&self, first: usize, second: usize, gap_ms: u64) {
+    let mut samples = self.samples.lock().unwrap();
+    let (Some(a), Some(b)) = (samples.get(first), samples.get(second)) else {
+      return;
+    };
+    let name = format!("{} + {}", a.name, b.name);
+    let events = merge_timelines(&[a, b], MergeStrategy::Sequential { gap_ms });
+    self.push_undo(&samples);
+    self.log(format!("Built {} with {} events", name, events.len()));
+    samples.push(Sample::new(name, events));
+  }
+
+  /// Write all samples as JSON. Returns how many events had keys that could not be stored.
+  /// A file saved by a newer version of the format is left untouched.
+  pub fn save_samples(&self, path: &Path) -> io::Result<usize> {
+    if persist::is_newer_version(path) {
+      self.newer_files.lock().unwrap().insert(path.to_path_buf());
+    }
+    let (count, skipped) = self.write_samples(path)?;
+    self.log(format!("Saved {} samples to {}", count, path.display()));
+    Ok(skipped)
+  }
+
+  /// Write sample `idx` to its own file in `dir`. Returns the path written.
+  pub fn save_sample_file(&self, idx: usize, dir: &Path) -> io::Result<PathBuf> {
+    let sample = self
+      .samples
+      .lock()
+      .unwrap()
+      .get(idx)
+      .cloned()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such sample"))?;
+    let micros = self.save_micros.load(Ordering::SeqCst);
+    let (path, skipped) = persist::save_sample_file(dir, &sample, micros)?;
+    if skipped > 0 {
+      self.log(format!("Skipped {} events with unsupported keys", skipped));
+    }
+    self.log(format!("Saved {} to {}", sample.name, path.display()));
+    Ok(path)
+  }
+
+  /// Replace the samples with every `*.json` sample file in `dir`. Files that fail
+  /// to parse are logged and left out. Returns how many samples were loaded.
+  pub fn load_library(&self, dir: &Path) -> io::Result<usize> {
+    let mut loaded = Vec::new();
+    for path in persist::library_files(dir)? {
+      match persist::load_sample_file(&path) {
+        Ok((sample, skipped)) => {
+          if skipped > 0 {
+            self.log(format!(
+              "{}: skipped {} events with unknown keys",
+              path.display(),
+              skipped
+            ));
+          }
+          loaded.push(sample);
+        }
+        Err(e) => self.log(format!("{}: {}", path.display(), e)),
+      }
+    }
+    self.rebase_imported(&mut loaded);
+    let count = loaded.len();
+    self.log(format!(
+      "Loaded {} samples from library {}",
+      count,
+      dir.display()
+    ));
+    let mut samples = self.samples.lock().unwrap();
+    self.push_undo(&samples);
+    *samples = loaded;
+    Ok(count)
+  }
+
+  /// Replace the active key map with the layout file at `path`.
+  pub fn load_key_map(&self, path: &Path) -> Result<(), KeyMapError> {
+    let map = KeyMap::load(path)?;
+    *self.key_map.lock().unwrap() = map;
+    self.log(format!("Loaded keyboard layout {}", path.display()));
+    Ok(())
+  }
+
+  /// The current preferences, as `save_settings` would write them.
+  pub fn settings(&self) -> Settings {
+    let window = *self.autorepeat_window_ms.lock().unwrap();
+    Settings {
+      record_hotkey: format!("{:?}", self.hotkey(Hotkey::Record)),
+      playback_hotkey: format!("{:?}", self.hotkey(Hotkey::Playback)),
+      panic_hotkey: format!("{:?}", self.hotkey(Hotkey::Panic)),
+      next_sample_hotkey: format!("{:?}", self.hotkey(Hotkey::NextSample)),
+      prev_sample_hotkey: format!("{:?}", self.hotkey(Hotkey::PrevSample)),
+      playback_offset_ms: *self.playback_offset_ms.lock().unwrap(),
+      clamp_offset: self.clamp_offset.load(Ordering::SeqCst),
+      playback_repeat: *self.playback_repeat.lock().unwrap(),
+      loop_gap_ms: *self.loop_gap_ms.lock().unwrap(),
+      jitter_ms: *self.jitter_ms.lock().unwrap(),
+      timing_mode: *self.timing_mode.lock().unwrap(),
+      min_gap_ms: *self.min_gap_ms.lock().unwrap(),
+      gap_mode: *self.gap_mode.lock().unwrap(),
+      ignore_autorepeat: self.ignore_autorepeat.load(Ordering::SeqCst),
+      autorepeat_window_ms: (window != AUTOREPEAT_WHILE_HELD).then_some(window),
+      debounce_ms: *self.debounce_ms.lock().unwrap(),
+      fold_typing: self.fold_typing.load(Ordering::SeqCst),
+      auto_stop_idle_ms: *self.auto_stop_idle_ms.lock().unwrap(),
+      save_micros: self.save_micros.load(Ordering::SeqCst),
+      rebase_on_import: self.rebase_on_import.load(Ordering::SeqCst),
+      tie_order: *self.tie_order.lock().unwrap(),
+      input_device: self.input_device(),
+      outliers: *self.outlier_method.lock().unwrap(),
+    }
+  }
+
+  /// Adopt `settings`. A hotkey name rdev doesn't know leaves that hotkey unchanged.
+  pub fn apply_settings(&self, settings: &Settings) {
+    for (which, name) in [
+      (Hotkey::Record, &settings.record_hotkey),
+      (Hotkey::Playback, &settings.playback_hotkey),
+      (Hotkey::Panic, &settings.panic_hotkey),
+      (Hotkey::NextSample, &settings.next_sample_hotkey),
+      (Hotkey::PrevSample, &settings.prev_sample_hotkey),
+    ] {
+      match keymap::rdev_key_by_name(name) {
+        Some(key) => *self.hotkey_slot(which).lock().unwrap() = key,
+        None => self.log(format!("Settings: unknown {:?} hotkey \"{}\"", which, name)),
+      }
+    }
+    *self.playback_offset_ms.lock().unwrap() = settings.playback_offset_ms;
+    self.clamp_offset.store(settings.clamp_offset, Ordering::SeqCst);
+    *self.playback_repeat.lock().unwrap() = settings.playback_repeat;
+    *self.loop_gap_ms.lock().unwrap() = settings.loop_gap_ms;
+    *self.jitter_ms.lock().unwrap() = settings.jitter_ms;
+    *self.timing_mode.lock().unwrap() = settings.timing_mode;
+    *self.min_gap_ms.lock().unwrap() = settings.min_gap_ms;
+    *self.gap_mode.lock().unwrap() = settings.gap_mode;
+    self.ignore_autorepeat.store(settings.ignore_autorepeat, Ordering::SeqCst);
+    *self.autorepeat_window_ms.lock().unwrap() =
+      settings.autorepeat_window_ms.unwrap_or(AUTOREPEAT_WHILE_HELD);
+    *self.debounce_ms.lock().unwrap() = settings.debounce_ms;
+    self.fold_typing.store(settings.fold_typing, Ordering::SeqCst);
+    *self.auto_stop_idle_ms.lock().unwrap() = settings.auto_stop_idle_ms;
+    self.save_micros.store(settings.save_micros, Ordering::SeqCst);
+    self.rebase_on_import.store(settings.rebase_on_import, Ordering::SeqCst);
+    *self.tie_order.lock().unwrap() = settings.tie_order;
+    *self.outlier_method.lock().unwrap() = settings.outliers;
+    self.set_input_device(settings.input_device.clone());
+  }
+
+  /// Apply the settings file, if there is one. A missing or unreadable file leaves the
+  /// defaults in place; the latter is logged.
+  pub fn load_settings(&self) {
+    let Some(path) = self.settings_path.lock().unwrap().clone() else {
+      return;
+    };
+    match Settings::load(&path) {
+      Ok(settings) => {
+        self.apply_settings(&settings);
+        self.log(format!("Loaded settings from {}", path.display()));
+      }
+      Err(settings::SettingsError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
+      Err(e) => self.log(format!("Using default settings: {}: {}", path.display(), e)),
+    }
+  }
+
+  /// Write the current settings to the settings path.
+  pub fn save_settings(&self) -> io::Result<()> {
+    let Some(path) = self.settings_path.lock().unwrap().clone() else {
+      return Ok(());
+    };
+    self.settings().save(&path)
+  }
+
+  /// Write all samples to the autosave path, creating its directory if needed.
+  pub fn autosave(&self) -> io::Result<()> {
+    let Some(path) = self.autosave_path.lock().unwrap().clone() else {
+      return Ok(());
+    };
+    let mut checked = self.autosave_checked.lock().unwrap();
+    if checked.as_ref() != Some(&path) {
+      if persist::is_newer_version(&path) {
+        self.newer_files.lock().unwrap().insert(path.clone());
+      }
+      *checked = Some(path.clone());
+    }
+    drop(checked);
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    self.write_samples(&path)?;
+    Ok(())
+  }
+
+  /// Serialize all samples to `path`. Returns the sample count and how many events
+  /// were skipped because their key has no stable name.
+  fn write_samples(&self, path: &Path) -> io::Result<(usize, usize)> {
+    if self.newer_files.lock().unwrap().contains(path) {
+      return Err(io::Error::other(format!(
+        "{} was saved by a newer version of Auto Note; not overwriting it",
+        path.display()
+      )));
+    }
+    let micros = self.save_micros.load(Ordering::SeqCst);
+    let samples = self.samples.lock().unwrap();
+    let skipped = persist::save_file(path, &samples, micros)?;
+    Ok((samples.len(), skipped))
+  }
+
+  /// Replace all samples with the ones in `path`. Returns how many events were skipped
+  /// because their key can't be mapped by this build.
+  pub fn load_samples(&self, path: &Path) -> Result<usize, LoadError> {
+    let mut loaded = match persist::load_file(path) {
+      Err(LoadError::NewerVersion(v)) => {
+        self.newer_files.lock().unwrap().insert(path.to_path_buf());
+        return Err(LoadError::NewerVersion(v));
+      }
+      other => other?,
+    };
+    self.rebase_imported(&mut loaded.samples);
+    if loaded.version == 0 {
+      self.log(format!("Migrating legacy save file {}", path.display()));
+    }
+    self.log(format!("Loaded {} samples from {}", loaded.samples.len(), path.display()));
+    let mut samples = self.samples.lock().unwrap();
+    self.push_undo(&samples);
+    *samples = loaded.samples;
+    Ok(loaded.skipped)
+  }
+
+  /// Write sample `idx` as an AutoHotkey script.
+  pub fn export_sample_ahk(&self, idx: usize, path: &Path) -> io::Result<()> {
+    self.export_sample_with(idx, path, export::export_ahk)
+  }
+
+  /// Write sample `idx` as `at_ms,action,key` CSV.
+  pub fn export_sample_csv(&self, idx: usize, path: &Path) -> io::Result<()> {
+    self.export_sample_with(idx, path, csv::to_csv)
+  }
+
+  /// Write sample `idx` as a MIDI file using the `midi_notes` mapping at `bpm`.
+  pub fn export_sample_midi(&self, idx: usize, path: &Path, bpm: f64) -> io::Result<()> {
+    let bytes = {
+      let samples = self.samples.lock().unwrap();
+      let Some(sample) = samples.get(idx) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no such sample"));
+      };
+      export::export_midi(&sample.events, &self.midi_notes.lock().unwrap(), bpm)
+    };
+    fs::write(path, bytes)?;
+    self.log(format!("Exported sample #{} to {}", idx + 1, path.display()));
+    Ok(())
+  }
+
+  /// Write sample `idx` as a `width` x `height` piano-roll PNG.
+  pub fn export_sample_png(
+    &self,
+    idx: usize,
+    path: &Path,
+    width: u32,
+    height: u32,
+  ) -> io::Result<()> {
+    let bytes = {
+      let samples = self.samples.lock().unwrap();
+      let Some(sample) = samples.get(idx) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no such sample"));
+      };
+      export::render_timeline_png(&sample.events, width, height)
+    };
+    fs::write(path, bytes)?;
+    self.log(format!("Exported sample #{} to {}", idx + 1, path.display()));
+    Ok(())
+  }
+
+  fn export_sample_with(
+    &self,
+    idx: usize,
+    path: &Path,
+    render: fn(&[TimedEvent]) -> String,
+  ) -> io::Result<()> {
+    let text = {
+      let samples = self.samples.lock().unwrap();
+      let Some(sample) = samples.get(idx) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no such sample"));
+      };
+      render(&sample.events)
+    };
+    fs::write(path, text)?;
+    self.log(format!("Exported sample #{} to {}", idx + 1, path.display()));
+    Ok(())
+  }
+
+  /// Append the CSV timeline in `path` as a new sample. Returns its event count.
+  pub fn import_csv(&self, path: &Path) -> io::Result<usize> {
+    let data = fs::read_to_string(path)?;
+    let events =
+      csv::from_csv(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let count = events.len();
+    let mut samples = self.samples.lock().unwrap();
+    self.push_undo(&samples);
+    let name = path
+      .file_stem()
+      .and_then(|s| s.to_str())
+      .map(str::to_string)
+      .unwrap_or_else(|| format!("Sample {}", samples.len() + 1));
+    let mut imported = [Sample::new(name, events)];
+    self.rebase_imported(&mut imported);
+    samples.extend(imported);
+    Ok(count)
+  }
+
+  /// Add a sample parsed from `key@ms` / `key^@ms` lines. Returns the event count.
+  pub fn import_text(&self, text: &str) -> Result<usize, ParseError> {
+    let events = text_timeline::parse_text_timeline(text)?;
+    let count = events.len();
+    let mut samples = self.samples.lock().unwrap();
+    self.push_undo(&samples);
+    let name = format!("Text {}", samples.len() + 1);
+    self.log(format!("Built {} with {} events", name, count));
+    samples.push(Sample::new(name, events));
+    Ok(count)
+  }
+
+  /// Move every event of every sample by `delta_ms`, unlike `playback_offset_ms`
+  /// which only applies while playing. Events pulled before t=0 stop at t=0.
+  pub fn apply_offset_to_all(&self, delta_ms: i64) {
+    let mut samples = self.samples.lock().unwrap();
+    if delta_ms == 0 || samples.is_empty() {
+      return;
+    }
+    self.push_undo(&samples);
+    for sample in samples.iter_mut() {
+      for ev in &mut sample.events {
+        ev.at = apply_offset(ev.at, delta_ms);
+      }
+      if let Some((start, end)) = &mut sample.loop_region {
+        *start = apply_offset(*start, delta_ms);
+        *end = apply_offset(*end, delta_ms);
+      }
+    }
+    self.log(format!("Shifted {} samples by {} ms", samples.len(), delta_ms));
+  }
+
+  /// Remove every sample. Undoable like any other edit.
+  pub fn clear_all_samples(&self) {
+    let mut samples = self.samples.lock().unwrap();
+    if samples.is_empty() {
+      return;
+    }
+    self.push_undo(&samples);
+    self.log(format!("Cleared {} samples", samples.len()));
+    samples.clear();
+  }
+
+  /// Add `events` to sample `idx`, keeping it sorted by time.
+  pub fn insert_events(&self, idx: usize, events: &[TimedEvent]) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx >= samples.len() {
+      return;
+    }
+    self.push_undo(&samples);
+    let sample = &mut samples[idx];
+    sample.events.extend_from_slice(events);
+    transform::sort_events(&mut sample.events, *self.tie_order.lock().unwrap());
+    self.log(format!("Added {} events to {}", events.len(), sample.name));
+  }
+
+  pub fn delete_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx < samples.len() {
+      self.push_undo(&samples);
+      let removed = samples.remove(idx);
+      self.log(format!("Deleted {}", removed.name));
+    }
+  }
+
+  /// Record the current samples so the next mutation can be undone.
+  fn push_undo(&self, samples: &[Sample]) {
+    let mut stack = self.undo_stack.lock().unwrap();
+    if stack.len() >= UNDO_DEPTH {
+      stack.remove(0);
+    }
+    stack.push(samples.to_vec());
+  }
+
+  /// Restore the samples as they were before the last mutation.
+  pub fn undo(&self) -> bool {
+    let Some(previous) = self.undo_stack.lock().unwrap().pop() else {
+      return false;
+    };
+    *self.samples.lock().unwrap() = previous;
+    self.log("Undo".into());
+    true
+  }
+
+  pub fn can_undo(&self) -> bool {
+    !self.undo_stack.lock().unwrap().is_empty()
+  }
+
+  /// Swap a sample with its neighbour above (`up`) or below.
+  pub fn move_sample(&self, idx: usize, up: bool) {
+    let mut samples = self.samples.lock().unwrap();
+    let target = if up { idx.checked_sub(1) } else { idx.checked_add(1) };
+    if let Some(target) = target
+      && idx < samples.len()
+      && target < samples.len()
+    {
+      self.push_undo(&samples);
+      samples.swap(idx, target);
+    }
+  }
+
+  /// Shift a sample so its first event is at t=0 and drop trailing pointer moves after
+  /// the last key or button event.
+  pub fn trim_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    if samples.get(idx).is_none_or(|s| s.events.is_empty()) {
+      return;
+    }
+    self.push_undo(&samples);
+    let sample = &mut samples[idx];
+    let first = sample.rebase_to_zero();
+    if let Some(last) = sample
+      .events
+      .iter()
+      .rposition(|e| !matches!(e.action, schema::KeyAction::MouseMove { .. }))
+    {
+      sample.events.truncate(last + 1);
+    }
+    self.log(format!("Trimmed {} by {} ms", sample.name, first.as_millis()));
+  }
+
+  /// Shift sample `idx` so its first event is at t=0, keeping everything else.
+  pub fn rebase_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    if samples.get(idx).is_none_or(|s| s.events.is_empty()) {
+      return;
+    }
+    self.push_undo(&samples);
+    let sample = &mut samples[idx];
+    let shift = sample.rebase_to_zero();
+    self.log(format!("Moved {} {} ms earlier", sample.name, shift.as_millis()));
+  }
+
+  /// Apply `rebase_on_import` to freshly loaded samples.
+  fn rebase_imported(&self, samples: &mut [Sample]) {
+    if !self.rebase_on_import.load(Ordering::SeqCst) {
+      return;
+    }
+    for sample in samples {
+      let shift = sample.rebase_to_zero();
+      if !shift.is_zero() {
+        self.log(format!("{}: moved {} ms earlier on import", sample.name, shift.as_millis()));
+      }
+    }
+  }
+
+  /// Per-slot timing distribution across all samples.
+  pub fn timing_stats(&self) -> Vec<SlotStats> {
+    let outliers = *self.outlier_method.lock().unwrap();
+    stats::timing_stats(&self.samples.lock().unwrap(), outliers)
+  }
+
+  /// Set sample `idx`'s offset so it lines up with sample `reference`.
+  pub fn align_sample(&self, idx: usize, reference: usize) -> Option<Alignment> {
+    let mut samples = self.samples.lock().unwrap();
+    let (Some(take), Some(target)) = (samples.get(idx), samples.get(reference)) else {
+      return None;
+    };
+    let Some(alignment) = stats::alignment(&target.events, &take.events) else {
+      self.log(format!("{} and {} share no key events", take.name, target.name));
+      return None;
+    };
+    self.push_undo(&samples);
+    let take = &mut samples[idx];
+    take.offset_ms = alignment.offset_ms;
+    self.log(format!(
+      "Aligned {}: offset {} ms, residual {:.1} ms over {} events",
+      take.name, alignment.offset_ms, alignment.residual_ms, alignment.matched
+    ));
+    Some(alignment)
+  }
+
+  /// Write the per-slot timing stats as JSON; `raw` includes every slot's values.
+  pub fn export_stats(&self, path: &Path, raw: bool) -> io::Result<()> {
+    let stats = self.timing_stats();
+    fs::write(path, stats::stats_to_json(&stats, raw)?)?;
+    self.log(format!("Exported {} stat slots to {}", stats.len(), path.display()));
+    Ok(())
+  }
+
+  /// Append a sample built from the per-slot mean timings of all samples.
+  pub fn build_averaged_sample(&self) {
+    let outliers = *self.outlier_method.lock().unwrap();
+    let mut samples = self.samples.lock().unwrap();
+    let events = stats::averaged_timeline(&samples, outliers);
+    if events.is_empty() {
+      self.log("Nothing to average: no key events recorded.".into());
+      return;
+    }
+    self.push_undo(&samples);
+    let name = format!("Averaged {}", samples.len() + 1);
+    self.log(format!("Built {} with {} events", name, events.len()));
+    samples.push(Sample::new(name, events));
+  }
+
+  /// Release every key left held at the end of a sample.
+  pub fn autofix_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx < samples.len() {
+      self.push_undo(&samples);
+    }
+    if let Some(sample) = samples.get_mut(idx) {
+      let added = validate::close_unmatched_downs(&mut sample.events);
+      self.log(format!("Auto-fix {}: added {} releases", sample.name, added));
+    }
+  }
+
+  /// Drop auto-repeat presses: any Down for a key that is already held.
+  pub fn dedup_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx < samples.len() {
+      self.push_undo(&samples);
+    }
+    if let Some(sample) = samples.get_mut(idx) {
+      let removed = validate::remove_repeated_downs(&mut sample.events);
+      self.log(format!("Dedup {}: removed {} repeated presses", sample.name, removed));
+    }
+  }
+
+  pub fn quantize_sample(&self, idx: usize, bpm: f64, division: u32) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx < samples.len() {
+      self.push_undo(&samples);
+    }
+    if let Some(sample) = samples.get_mut(idx) {
+      transform::quantize(&mut sample.events, bpm, division);
+      self.log(format!("Quantized {} to {} BPM 1/{}", sample.name, bpm, division));
+    }
+  }
+
+  /// Soften direction changes between the movement `keys` in a sample.
+  pub fn smooth_movement_sample(&self, idx: usize, keys: &[enigo::Key], factor: f64) {
+    let mut samples = self.samples.lock().unwrap();
+    let Some(sample) = samples.get(idx) else {
+      return;
+    };
+    let smoothed = transform::smooth_movement(&sample.events, keys, factor);
+    let added = smoothed.len() - sample.events.len();
+    if added == 0 {
+      self.log(format!("{}: no direction changes to smooth", sample.name));
+      return;
+    }
+    self.push_undo(&samples);
+    let sample = &mut samples[idx];
+    sample.events = smoothed;
+    self.log(format!("Smoothed movement in {} ({} events added)", sample.name, added));
+  }
+
+  /// Set every key hold in a sample to `target_ms`.
+  pub fn normalize_holds_sample(&self, idx: usize, target_ms: u64) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx < samples.len() {
+      self.push_undo(&samples);
+    }
+    if let Some(sample) = samples.get_mut(idx) {
+      transform::normalize_holds(&mut sample.events, target_ms);
+      self.log(format!("Normalized holds in {} to {} ms", sample.name, target_ms));
+    }
+  }
+
+  /// Stretch or compress a sample's timing around `anchor_ms`.
+  pub fn time_scale_sample(&self, idx: usize, factor: f64, anchor_ms: u64) {
+    let mut samples = self.samples.lock().unwrap();
+    if idx < samples.len() {
+      self.push_undo(&samples);
+    }
+    if let Some(sample) = samples.get_mut(idx) {
+      transform::time_scale(&mut sample.events, factor, anchor_ms);
+      self.log(format!(
+        "Scaled {} by {}x around {} ms",
+        sample.name, factor, anchor_ms
+      ));
+    }
+  }
+
+  /// Append a backwards copy of a sample as a new sample.
+  pub fn reverse_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    let Some(sample) = samples.get(idx) else {
+      return;
+    };
+    let name = format!("{} (reversed)", sample.name);
+    let events = transform::reverse_timeline(&sample.events);
+    self.push_undo(&samples);
+    self.log(format!("Built {} with {} events", name, events.len()));
+    samples.push(Sample::new(name, events));
+  }
+
+  /// Add a copy of sample `idx` with its independent key presses reordered.
+  pub fn vary_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    let Some(sample) = samples.get(idx) else {
+      return;
+    };
+    let seed = fresh_seed();
+    let name = format!("{} (varied)", sample.name);
+    let events = transform::shuffle_independent(&sample.events, seed);
+    self.push_undo(&samples);
+    self.log(format!("Built {} with seed {}", name, seed));
+    samples.push(Sample::new(name, events));
+  }
+
+  /// Insert a copy of sample `idx` right after it, named "<name> copy".
+  pub fn clone_sample(&self, idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    let Some(sample) = samples.get(idx) else {
+      return;
+    };
+    let mut copy = sample.clone();
+    copy.name = format!("{} copy", sample.name);
+    self.push_undo(&samples);
+    self.log(format!("Duplicated {} as {}", samples[idx].name, copy.name));
+    samples.insert(idx + 1, copy);
+  }
+
+  /// Add `tag` to a sample unless it is blank or already present.
+  pub fn add_tag(&self, idx: usize, tag: &str) {
+    let tag = tag.trim();
+    let mut samples = self.samples.lock().unwrap();
+    if let Some(sample) = samples.get_mut(idx)
+      && !tag.is_empty()
+      && !sample.tags.iter().any(|t| t == tag)
+    {
+      sample.tags.push(tag.to_string());
+    }
+  }
+
+  /// Delete a single event from a sample in place.
+  pub fn remove_event(&self, sample_idx: usize, event_idx: usize) {
+    let mut samples = self.samples.lock().unwrap();
+    if sample_idx < samples.len() && event_idx < samples[sample_idx].events.len() {
+      self.push_undo(&samples);
+      let sample = &mut samples[sample_idx];
+      let removed = sample.events.remove(event_idx);
+      self.log(format!(
+        "Removed event #{} ({:?}) from {}",
+        event_idx, removed.action, sample.name
+      ));
+    }
+  }
+
+  /// Move one event to `at_ms` and re-sort the sample. A press can't be moved past its
+  /// release (or a release before its press); such moves stop at the partner event.
+  pub fn set_event_time(&self, sample_idx: usize, event_idx: usize, at_ms: u64) {
+    let mut samples = self.samples.lock().unwrap();
+    let Some(ev) = samples.get(sample_idx).and_then(|s| s.events.get(event_idx)) else {
+      return;
+    };
+    let mut at = Duration::from_millis(at_ms);
+    if at == ev.at {
+      return;
+    }
+    let events = &samples[sample_idx].events;
+    match partner_event(events, event_idx) {
+      Some(p) if p > event_idx && at > events[p].at => at = events[p].at,
+      Some(p) if p < event_idx && at < events[p].at => at = events[p].at,
+      _ => {}
+    }
+    self.push_undo(&samples);
+    let sample = &mut samples[sample_idx];
+    let mut moved = sample.events.remove(event_idx);
+    self.log(format!(
+      "Moved {} in {} from {} to {} ms",
+      action_label(&moved.action),
+      sample.name,
+      moved.at.as_millis(),
+      at.as_millis()
+    ));
+    moved.at = at;
+    // Insert after any events at the same time so ties keep their recorded order.
+    let pos = sample.events.partition_point(|e| e.at <= at);
+    sample.events.insert(pos, moved);
+  }
+
+  /// Delete several events from a sample at once, e.g. a whole chord.
+  pub fn remove_events(&self, sample_idx: usize, event_indices: &[usize]) {
+    let mut samples = self.samples.lock().unwrap();
+    if sample_idx >= samples.len() || event_indices.is_empty() {
+      return;
+    }
+    self.push_undo(&samples);
+    let sample = &mut samples[sample_idx];
+    let mut index = 0;
+    sample.events.retain(|_| {
+      let keep = !event_indices.contains(&index);
+      index += 1;
+      keep
+    });
+    self.log(format!("Removed {} events from {}", event_indices.len(), sample.name));
+  }
+
+  /// Freeze an in-flight playback where it is; `resume_playback` continues it.
+  pub fn pause_playback(&self) {
+    if self.playing.load(Ordering::SeqCst) && !self.playback_paused.swap(true, Ordering::SeqCst) {
+      self.log("Playback paused".into());
+    }
+  }
+
+  pub fn resume_playback(&self) {
+    if self.playback_paused.swap(false, Ordering::SeqCst) {
+      self.log("Playback resumed".into());
+    }
   }
 
   pub fn stop_playback(&self) {
     self.playback_stop.store(true, Ordering::SeqCst);
-    if let Some(handle) = self.playback_handle.lock().unwrap().take() {
+    self.playback_paused.store(false, Ordering::SeqCst);
+    // Release the lock before joining so concurrent callers never wait on it.
+    let handle = self.playback_handle.lock().unwrap().take();
+    // Only a run cut short can leave a modifier latched; a finished one released its
+    // keys, a dry run sent none, and with nothing playing a release would drop
+    // modifiers the user holds.
+    let interrupted = handle.as_ref().is_some_and(|h| !h.is_finished());
+    if let Some(handle) = handle {
       let _ = handle.join();
     }
     self.playing.store(false, Ordering::SeqCst);
+    if interrupted && !self.dry_run.load(Ordering::SeqCst) {
+      self.release_all_modifiers();
+    }
+  }
+
+  /// Release Shift, Control, Alt and Meta at the OS level, including ones latched by
+  /// something other than the current playback. A running playback is stopped first,
+  /// since it holds the input sender until it ends.
+  pub fn release_all_modifiers(&self) {
+    let running = self
+      .playback_handle
+      .lock()
+      .unwrap()
+      .as_ref()
+      .is_some_and(|h| !h.is_finished());
+    if running {
+      // Stopping a running playback releases the modifiers itself.
+      self.stop_playback();
+      return;
+    }
+    macro_play::release_modifiers();
+  }
+
+  /// Join a playback that has finished on its own, so its thread does not linger
+  /// until the next stop. Cheap to call every frame.
+  pub fn reap_playback(&self) {
+    let mut slot = self.playback_handle.lock().unwrap();
+    if slot.as_ref().is_some_and(|h| h.is_finished()) {
+      if let Some(handle) = slot.take() {
+        let _ = handle.join();
+      }
+      self.playing.store(false, Ordering::SeqCst);
+    }
+  }
+
+  /// Emergency stop: end recording and playback; the playback thread releases
+  /// everything it still holds before exiting.
+  pub fn panic_stop(&self) {
+    self.stop_recording();
+    self.stop_playback();
+  }
+
+  pub fn hotkey(&self, which: Hotkey) -> Key {
+    *self.hotkey_slot(which).lock().unwrap()
+  }
+
+  /// Bind `which` to whatever key is pressed next.
+  pub fn begin_rebind(&self, which: Hotkey) {
+    *self.rebinding.lock().unwrap() = Some(which);
+  }
+
+  /// Whether `key` is bound to a hotkey the user moved off its default; such keys are
+  /// never recorded. Keys at their default binding only have their press swallowed by
+  /// `handle_hotkey`, so a default such as Esc is recorded again once it is rebound.
+  fn is_rebound_hotkey(&self, key: Key) -> bool {
+    Hotkey::ALL
+      .into_iter()
+      .any(|which| self.hotkey(which) == key && key != which.default_key())
+  }
+
+  fn hotkey_slot(&self, which: Hotkey) -> &Arc<Mutex<Key>> {
+    match which {
+      Hotkey::Record => &self.record_hotkey,
+      Hotkey::Playback => &self.playback_hotkey,
+      Hotkey::Panic => &self.panic_hotkey,
+      Hotkey::NextSample => &self.next_sample_hotkey,
+      Hotkey::PrevSample => &self.prev_sample_hotkey,
+    }
   }
 
   pub fn tracked_keys(&self) -> Vec<enigo::Key> {
@@ -154,6 +1759,7 @@ impl AppState {
         keys.push(key);
       }
     }
+    self.sync_allowed_keys(&keys);
   }
 
   pub fn remove_tracked_key(&self, idx: usize) {
@@ -161,6 +1767,66 @@ impl AppState {
     if idx < keys.len() {
       keys.remove(idx);
     }
+    self.sync_allowed_keys(&keys);
+  }
+
+  pub fn restricts_to_tracked(&self) -> bool {
+    self.allowed_keys.lock().unwrap().is_some()
+  }
+
+  /// Limit recording to the tracked keys, or lift the restriction.
+  pub fn set_restrict_to_tracked(&self, restrict: bool) {
+    *self.allowed_keys.lock().unwrap() = restrict.then(|| self.tracked_keys());
+  }
+
+  fn sync_allowed_keys(&self, tracked: &[enigo::Key]) {
+    let mut allowed = self.allowed_keys.lock().unwrap();
+    if allowed.is_some() {
+      *allowed = Some(tracked.to_vec());
+    }
+  }
+
+  /// Whether a Down for `key`, arriving while it is already held, should be dropped.
+  fn is_autorepeat(&self, key: &enigo::Key, was_pressed: bool) -> bool {
+    if !was_pressed || !self.ignore_autorepeat.load(Ordering::SeqCst) {
+      return false;
+    }
+    let window = *self.autorepeat_window_ms.lock().unwrap();
+    match self.last_downs.lock().unwrap().get(key) {
+      Some(last) => last.elapsed() < Duration::from_millis(window),
+      None => window == AUTOREPEAT_WHILE_HELD,
+    }
+  }
+
+  /// With `debounce_ms` set, whether a Down (`down`) or Up of `key` undoes the key's
+  /// last recorded event within the window. That event is removed and the caller drops
+  /// this one, so the bounce leaves no trace.
+  fn debounce(&self, key: enigo::Key, down: bool, start: Instant) -> bool {
+    let window = *self.debounce_ms.lock().unwrap();
+    if window == 0 {
+      return false;
+    }
+    let mut events = self.current_events.lock().unwrap();
+    let last = events.iter().rposition(|e| {
+      matches!(e.action, schema::KeyAction::Down(k) | schema::KeyAction::Up(k) if k == key)
+    });
+    let Some(pos) = last else {
+      return false;
+    };
+    let reverses = matches!(events[pos].action, schema::KeyAction::Up(_)) == down;
+    let gap = start.elapsed().saturating_sub(events[pos].at);
+    if reverses && gap < Duration::from_millis(window) {
+      events.remove(pos);
+      return true;
+    }
+    false
+  }
+
+  fn is_key_allowed(&self, key: &enigo::Key) -> bool {
+    match &*self.allowed_keys.lock().unwrap() {
+      Some(allowed) => allowed.contains(key),
+      None => true,
+    }
   }
 
   pub fn tracked_key_states(&self) -> Vec<(enigo::Key, bool)> {
@@ -174,32 +1840,84 @@ impl AppState {
 }
 
 pub fn handle_event(state: &AppState, event: Event) {
-  // Hotkeys: F9 toggle record, F10 toggle playback.
-  if let EventType::KeyPress(key) = event.event_type {
-    match key {
-      Key::F9 => {
-        if state.recording.load(Ordering::SeqCst) {
-          state.stop_recording();
-          println!("Recording stopped via F9");
-        } else {
-          state.start_recording();
-          println!("Recording started via F9");
-        }
-        return;
+  if handle_hotkey(state, &event) {
+    return;
+  }
+  record_input(state, event);
+}
+
+/// Act on `event` if it is a hotkey press (or the key picked while rebinding). Returns
+/// whether it was consumed.
+pub fn handle_hotkey(state: &AppState, event: &Event) -> bool {
+  // Hotkeys: panic stop (Esc by default) first so it always wins, then record toggle
+  // (F9 by default), playback toggle (F10 by default) and active sample cycling (F7
+  // and F8 by default).
+  let EventType::KeyPress(key) = event.event_type else {
+    return false;
+  };
+  if key == state.hotkey(Hotkey::Panic) {
+    state.rebinding.lock().unwrap().take();
+    state.panic_stop();
+    state.log(format!("Emergency stop via {:?}", key));
+    return true;
+  }
+  if let Some(which) = state.rebinding.lock().unwrap().take() {
+    *state.hotkey_slot(which).lock().unwrap() = key;
+    state.log(format!("{:?} hotkey bound to {:?}", which, key));
+    return true;
+  }
+  if key == state.hotkey(Hotkey::Record) {
+    if state.recording.load(Ordering::SeqCst) {
+      state.stop_recording();
+      state.log(format!("Recording stopped via {:?}", key));
+    } else {
+      state.start_recording();
+      state.log(format!("Recording started via {:?}", key));
+    }
+    return true;
+  }
+  if key == state.hotkey(Hotkey::Playback) {
+    if state.playing.load(Ordering::SeqCst) {
+      state.stop_playback();
+      state.log(format!("Playback stopped via {:?}", key));
+    } else {
+      state.stop_recording();
+      state.playback_active();
+      state.log(format!("Playback started via {:?}", key));
+    }
+    return true;
+  }
+  if key == state.hotkey(Hotkey::NextSample) {
+    state.cycle_active_sample(1);
+    return true;
+  }
+  if key == state.hotkey(Hotkey::PrevSample) {
+    state.cycle_active_sample(-1);
+    return true;
+  }
+  false
+}
+
+/// Update key states and the mouse position from `event`, and add it to the recording
+/// if one is running. Rebound hotkeys are never recorded.
+pub fn record_input(state: &AppState, event: Event) {
+  // Key states drive the overlay, so they follow the keyboard even when not recording.
+  let mut was_pressed = false;
+  match event.event_type {
+    EventType::KeyPress(key) => {
+      if let Some(mapped) = state.key_map.lock().unwrap().get(key) {
+        was_pressed = state.key_states.lock().unwrap().insert(mapped, true) == Some(true);
       }
-      Key::F10 => {
-        if state.playing.load(Ordering::SeqCst) {
-          state.stop_playback();
-          println!("Playback stopped via F10");
-        } else {
-          state.stop_recording();
-          state.playback_latest();
-          println!("Playback started via F10");
-        }
-        return;
+    }
+    EventType::KeyRelease(key) => {
+      if let Some(mapped) = state.key_map.lock().unwrap().get(key) {
+        state.key_states.lock().unwrap().insert(mapped, false);
       }
-      _ => {}
     }
+    EventType::MouseMove { x, y } => {
+      *state.mouse_pos.lock().unwrap() = Some((x, y));
+    }
+    _ => {}
   }
 
   if !state.recording.load(Ordering::SeqCst) {
@@ -209,34 +1927,125 @@ pub fn handle_event(state: &AppState, event: Event) {
   let Some(start_at) = *state.start.lock().unwrap() else {
     return;
   };
+  if !matches!(event.event_type, EventType::MouseMove { .. }) {
+    *state.last_activity.lock().unwrap() = Some(Instant::now());
+  }
+  // handle_hotkey only swallows a hotkey's press; the release of a rebound one (and,
+  // from a device listener, the press too) must not end up in the sample either,
+  // which matters once a hotkey is bound to a letter.
+  if let EventType::KeyPress(key) | EventType::KeyRelease(key) = event.event_type
+    && state.is_rebound_hotkey(key)
+  {
+    return;
+  }
 
   match event.event_type {
     EventType::KeyPress(key) => {
-      if let Some(mapped) = convert_key(key) {
-        // Always update key state for overlay
-        state.key_states.lock().unwrap().insert(mapped, true);
+      if let Some(mapped) = state.key_map.lock().unwrap().get(key) {
+        let autorepeat = state.is_autorepeat(&mapped, was_pressed);
         // Record only when recording is active
-        if state.recording.load(Ordering::SeqCst) {
+        if state.recording.load(Ordering::SeqCst)
+          && state.is_key_allowed(&mapped)
+          && !autorepeat
+          && !state.debounce(mapped, true, start_at)
+        {
           push_event(schema::KeyAction::Down(mapped), start_at, &state.current_events);
+          state.last_downs.lock().unwrap().insert(mapped, Instant::now());
         }
       } else {
-        println!("record: unmapped keypress {:?}", key);
+        state.unmapped_count.fetch_add(1, Ordering::SeqCst);
+        state.log(format!("record: unmapped keypress {:?}", key));
       }
     }
     EventType::KeyRelease(key) => {
-      if let Some(mapped) = convert_key(key) {
-        state.key_states.lock().unwrap().insert(mapped, false);
-        if state.recording.load(Ordering::SeqCst) {
+      if let Some(mapped) = state.key_map.lock().unwrap().get(key) {
+        if state.recording.load(Ordering::SeqCst)
+          && state.is_key_allowed(&mapped)
+          && !state.debounce(mapped, false, start_at)
+        {
           push_event(schema::KeyAction::Up(mapped), start_at, &state.current_events);
         }
       } else {
-        println!("record: unmapped keyrelease {:?}", key);
+        state.log(format!("record: unmapped keyrelease {:?}", key));
+      }
+    }
+    EventType::MouseMove { x, y } => {
+      push_mouse_move(state, x, y, start_at, false);
+    }
+    EventType::ButtonPress(button) => {
+      if let Some(mapped) = convert_button(button) {
+        // Make sure the click lands where it happened even if the last move was thinned out.
+        if let Some((x, y)) = *state.mouse_pos.lock().unwrap() {
+          push_mouse_move(state, x, y, start_at, true);
+        }
+        push_event(schema::KeyAction::ButtonDown(mapped), start_at, &state.current_events);
+      } else {
+        state.log(format!("record: unmapped button press {:?}", button));
+      }
+    }
+    EventType::ButtonRelease(button) => {
+      if let Some(mapped) = convert_button(button) {
+        if let Some((x, y)) = *state.mouse_pos.lock().unwrap() {
+          push_mouse_move(state, x, y, start_at, true);
+        }
+        push_event(schema::KeyAction::ButtonUp(mapped), start_at, &state.current_events);
+      } else {
+        state.log(format!("record: unmapped button release {:?}", button));
       }
     }
     _ => {}
   }
 }
 
+/// Index of the release matching the press at `idx`, or of the press matching the
+/// release at `idx`, for keys, mouse buttons and controller buttons.
+fn partner_event(events: &[TimedEvent], idx: usize) -> Option<usize> {
+  use schema::KeyAction::{ButtonDown, ButtonUp, Down, GamepadDown, GamepadUp, Up};
+  let same = |a: &schema::KeyAction, b: &schema::KeyAction| match (a, b) {
+    (Down(x) | Up(x), Down(y) | Up(y)) => x == y,
+    (ButtonDown(x) | ButtonUp(x), ButtonDown(y) | ButtonUp(y)) => x == y,
+    (GamepadDown(x) | GamepadUp(x), GamepadDown(y) | GamepadUp(y)) => x == y,
+    _ => false,
+  };
+  let action = events[idx].action;
+  match action {
+    Down(_) | ButtonDown(_) | GamepadDown(_) => events[idx + 1..]
+      .iter()
+      .position(|e| same(&e.action, &action))
+      .map(|i| idx + 1 + i)
+      .filter(|&i| matches!(events[i].action, Up(_) | ButtonUp(_) | GamepadUp(_))),
+    Up(_) | ButtonUp(_) | GamepadUp(_) => events[..idx]
+      .iter()
+      .rposition(|e| same(&e.action, &action))
+      .filter(|&i| matches!(events[i].action, Down(_) | ButtonDown(_) | GamepadDown(_))),
+    _ => None,
+  }
+}
+
+/// Record a pointer move unless it is within the distance/time threshold of the last one.
+/// `force` bypasses the threshold (still skipping exact duplicates).
+fn push_mouse_move(state: &AppState, x: f64, y: f64, start: Instant, force: bool) {
+  let elapsed = Instant::now().duration_since(start);
+  let mut last = state.last_mouse_move.lock().unwrap();
+  if let Some((lx, ly, last_at)) = *last {
+    let distance = ((x - lx).powi(2) + (y - ly).powi(2)).sqrt();
+    if distance == 0.0 {
+      return;
+    }
+    let too_close = distance < MOUSE_MIN_DISTANCE_PX;
+    let too_soon = elapsed.saturating_sub(last_at) < MOUSE_MIN_INTERVAL;
+    if !force && (too_close || too_soon) {
+      return;
+    }
+  }
+  *last = Some((x, y, elapsed));
+  state
+    .current_events
+    .lock()
+    .unwrap()
+    .push(TimedEvent { at: elapsed, action: schema::KeyAction::MouseMove { x, y } });
+}
+
 fn push_event(action: schema::KeyAction, start: Instant, sink: &Arc<Mutex<Vec<TimedEvent>>>) {
   let elapsed = Instant::now().duration_since(start);
   sink.lock()
@@ -244,13 +2053,100 @@ fn push_event(action: schema::KeyAction, start: Instant, sink: &Arc<Mutex<Vec<Ti
       .push(TimedEvent { at: elapsed, action });
 }
 
-fn log_recorded_events(events: &[TimedEvent]) {
-  println!("Recorded {} events:", events.len());
+fn log_recorded_events(state: &AppState, events: &[TimedEvent]) {
+  state.log(format!("Recorded {} events:", events.len()));
   for (i, ev) in events.iter().enumerate() {
-    println!("  #{:<3} at {:>6} ms => {:?}", i, ev.at.as_millis(), ev.action);
+    state.log(format!("  #{:<3} at {:>6} ms => {:?}", i, ev.at.as_millis(), ev.action));
   }
 }
 
+fn merge_timelines(samples: &[&Sample], strategy: MergeStrategy) -> Vec<TimedEvent> {
+  let mut merged = Vec::new();
+  match strategy {
+    MergeStrategy::Overlay => {
+      merged.extend(samples.iter().flat_map(|s| s.events.iter().copied()));
+      transform::sort_events(&mut merged, TieOrder::default());
+    }
+    MergeStrategy::Sequential { gap_ms } => {
+      let mut start = Duration::ZERO;
+      for sample in samples {
+        merged.extend(sample.events.iter().map(|e| TimedEvent {
+          at: start + e.at,
+          action: e.action,
+        }));
+        start += sample.duration() + Duration::from_millis(gap_ms);
+      }
+    }
+  }
+  merged
+}
+
+/// User-facing explanation of why input capture could not start.
+fn listener_error_message(error: &rdev::ListenError) -> String {
+  match error {
+    // On macOS the event tap is refused when the app lacks Accessibility permission.
+    rdev::ListenError::EventTapError => {
+      "Input capture unavailable — grant Accessibility permission in System Settings > \
+       Privacy & Security > Accessibility, then restart"
+        .into()
+    }
+    rdev::ListenError::MissingDisplayError => {
+      "Input capture unavailable — no X display to listen on".into()
+    }
+    rdev::ListenError::KeyHookError(code) | rdev::ListenError::MouseHookError(code) => {
+      format!("Input capture unavailable — installing the input hook failed (error {})", code)
+    }
+    other => format!("Input capture unavailable ({:?})", other),
+  }
+}
+
+/// Title of the focused window, or `None` where the platform doesn't expose it.
+fn foreground_window_title() -> Option<String> {
+  active_win_pos_rs::get_active_window()
+    .ok()
+    .map(|w| w.title)
+    .filter(|t| !t.is_empty())
+}
+
+/// `autosave.json` in the per-user config directory, if the OS provides one.
+fn default_autosave_path() -> Option<PathBuf> {
+  directories::ProjectDirs::from("", "", "auto-note")
+    .map(|dirs| dirs.config_dir().join("autosave.json"))
+}
+
+/// Current wall-clock time in ms since the Unix epoch; `None` if the clock is before it.
+fn unix_millis() -> Option<u64> {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .ok()
+    .map(|d| d.as_millis() as u64)
+}
+
+/// Format ms since the Unix epoch as `YYYY-MM-DD HH:MM` UTC.
+pub fn format_timestamp(ms: u64) -> String {
+  let secs = ms / 1000;
+  let (hour, minute) = (secs / 3600 % 24, secs / 60 % 60);
+  // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+  let z = (secs / 86_400) as i64 + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute)
+}
+
+/// Seed for a new playback's jitter RNG.
+fn fresh_seed() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_nanos() as u64)
+    .unwrap_or(0)
+}
+
 fn apply_offset(at: Duration, offset_ms: i64) -> Duration {
   if offset_ms >= 0 {
     at + Duration::from_millis(offset_ms as u64)
@@ -259,62 +2155,29 @@ fn apply_offset(at: Duration, offset_ms: i64) -> Duration {
   }
 }
 
-fn convert_key(key: Key) -> Option<enigo::Key> {
-  // rdev Key -> enigo Key mapping. Return None if unknown to avoid sending spaces.
-  let mapped = match key {
-    Key::KeyA => enigo::Key::Layout('a'),
-    Key::KeyB => enigo::Key::Layout('b'),
-    Key::KeyC => enigo::Key::Layout('c'),
-    Key::KeyD => enigo::Key::Layout('d'),
-    Key::KeyE => enigo::Key::Layout('e'),
-    Key::KeyF => enigo::Key::Layout('f'),
-    Key::KeyG => enigo::Key::Layout('g'),
-    Key::KeyH => enigo::Key::Layout('h'),
-    Key::KeyI => enigo::Key::Layout('i'),
-    Key::KeyJ => enigo::Key::Layout('j'),
-    Key::KeyK => enigo::Key::Layout('k'),
-    Key::KeyL => enigo::Key::Layout('l'),
-    Key::KeyM => enigo::Key::Layout('m'),
-    Key::KeyN => enigo::Key::Layout('n'),
-    Key::KeyO => enigo::Key::Layout('o'),
-    Key::KeyP => enigo::Key::Layout('p'),
-    Key::KeyQ => enigo::Key::Layout('q'),
-    Key::KeyR => enigo::Key::Layout('r'),
-    Key::KeyS => enigo::Key::Layout('s'),
-    Key::KeyT => enigo::Key::Layout('t'),
-    Key::KeyU => enigo::Key::Layout('u'),
-    Key::KeyV => enigo::Key::Layout('v'),
-    Key::KeyW => enigo::Key::Layout('w'),
-    Key::KeyX => enigo::Key::Layout('x'),
-    Key::KeyY => enigo::Key::Layout('y'),
-    Key::KeyZ => enigo::Key::Layout('z'),
-    Key::Num0 => enigo::Key::Layout('0'),
-    Key::Num1 => enigo::Key::Layout('1'),
-    Key::Num2 => enigo::Key::Layout('2'),
-    Key::Num3 => enigo::Key::Layout('3'),
-    Key::Num4 => enigo::Key::Layout('4'),
-    Key::Num5 => enigo::Key::Layout('5'),
-    Key::Num6 => enigo::Key::Layout('6'),
-    Key::Num7 => enigo::Key::Layout('7'),
-    Key::Num8 => enigo::Key::Layout('8'),
-    Key::Num9 => enigo::Key::Layout('9'),
-    Key::Space => enigo::Key::Space,
-    Key::Return => enigo::Key::Return,
-    Key::Backspace => enigo::Key::Backspace,
-    Key::Tab => enigo::Key::Tab,
-    Key::Escape => enigo::Key::Escape,
-    Key::UpArrow => enigo::Key::UpArrow,
-    Key::DownArrow => enigo::Key::DownArrow,
-    Key::LeftArrow => enigo::Key::LeftArrow,
-    Key::RightArrow => enigo::Key::RightArrow,
-    Key::ShiftLeft | Key::ShiftRight => enigo::Key::Shift,
-    Key::ControlLeft | Key::ControlRight => enigo::Key::Control,
-    Key::Alt | Key::AltGr => enigo::Key::Alt,
-    _ => return None,
+fn convert_button(button: Button) -> Option<enigo::MouseButton> {
+  let mapped = match button {
+    Button::Left => enigo::MouseButton::Left,
+    Button::Right => enigo::MouseButton::Right,
+    Button::Middle => enigo::MouseButton::Middle,
+    Button::Unknown(_) => return None,
   };
   Some(mapped)
 }
 
+pub fn action_label(action: &schema::KeyAction) -> String {
+  match action {
+    schema::KeyAction::Down(k) => format!("{} down", key_label(k)),
+    schema::KeyAction::Up(k) => format!("{} up", key_label(k)),
+    schema::KeyAction::MouseMove { x, y } => format!("move ({:.0}, {:.0})", x, y),
+    schema::KeyAction::ButtonDown(b) => format!("{:?} click down", b),
+    schema::KeyAction::ButtonUp(b) => format!("{:?} click up", b),
+    schema::KeyAction::Type(c) => format!("type {:?}", c),
+    schema::KeyAction::GamepadDown(b) => format!("pad {:?} down", b),
+    schema::KeyAction::GamepadUp(b) => format!("pad {:?} up", b),
+  }
+}
+
 pub fn key_label(key: &enigo::Key) -> String {
   match key {
     enigo::Key::Layout(c) => format!("{}", c),
@@ -333,3 +2196,153 @@ pub fn key_label(key: &enigo::Key) -> String {
     other => format!("{:?}", other),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ev(ms: u64, action: schema::KeyAction) -> TimedEvent {
+    TimedEvent { at: Duration::from_millis(ms), action }
+  }
+
+  /// A state that never touches the config directory.
+  fn test_state() -> AppState {
+    let state = AppState::new();
+    *state.autosave_path.lock().unwrap() = None;
+    *state.settings_path.lock().unwrap() = None;
+    state
+  }
+
+  #[test]
+  fn appended_take_starts_after_the_sample_ends() {
+    use schema::KeyAction::{Down, Up};
+    let (a, b) = (enigo::Key::Layout('a'), enigo::Key::Layout('b'));
+    let state = test_state();
+    let old = vec![ev(0, Down(a)), ev(100, Up(a))];
+    state.samples.lock().unwrap().push(Sample::new("take".into(), old));
+    state.continue_recording(0);
+    *state.current_events.lock().unwrap() = vec![ev(0, Down(b)), ev(40, Up(b))];
+    state.stop_recording();
+    let samples = state.samples.lock().unwrap();
+    assert_eq!(samples.len(), 1);
+    let gap = APPEND_GAP.as_millis() as u64;
+    assert_eq!(
+      samples[0].events,
+      vec![
+        ev(0, Down(a)),
+        ev(100, Up(a)),
+        ev(100 + gap, Down(b)),
+        ev(140 + gap, Up(b)),
+      ]
+    );
+  }
+
+  /// A save file in a fresh temp directory claiming a format newer than this build's.
+  fn newer_save_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("auto-note-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("samples.json");
+    let data = format!(r#"{{"version": {}, "samples": []}}"#, persist::SAVE_VERSION + 1);
+    fs::write(&path, data).unwrap();
+    path
+  }
+
+  #[test]
+  fn autosave_does_not_overwrite_a_newer_save_file() {
+    let path = newer_save_file("autosave-newer");
+    let before = fs::read_to_string(&path).unwrap();
+    let state = test_state();
+    state.samples.lock().unwrap().push(Sample::new("take".into(), Vec::new()));
+    *state.autosave_path.lock().unwrap() = Some(path.clone());
+    assert!(state.autosave().is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn save_samples_does_not_overwrite_a_newer_save_file() {
+    let path = newer_save_file("save-newer");
+    let before = fs::read_to_string(&path).unwrap();
+    let state = test_state();
+    state.samples.lock().unwrap().push(Sample::new("take".into(), Vec::new()));
+    assert!(state.save_samples(&path).is_err());
+    assert!(matches!(state.load_samples(&path), Err(LoadError::NewerVersion(_))));
+    assert!(state.save_samples(&path).is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn rapid_play_stop_leaves_nothing_running_or_held() {
+    use schema::KeyAction::{Down, Up};
+    let a = enigo::Key::Layout('a');
+    let state = test_state();
+    state.dry_run.store(true, Ordering::SeqCst);
+    let sample = Sample::new("tap".into(), vec![ev(0, Down(a)), ev(3, Up(a))]);
+    state.log_lines.lock().unwrap().clear();
+    for i in 0..40 {
+      state.playback_sample(&sample);
+      thread::sleep(Duration::from_millis(i % 5));
+      state.stop_playback();
+      // stop_playback joined the watcher, which joined the player: no thread is left.
+      assert!(state.playback_handle.lock().unwrap().is_none());
+      assert!(!state.playing.load(Ordering::SeqCst));
+    }
+    // Every press was either played out or released when its run was stopped.
+    let log = state.log_lines.lock().unwrap();
+    let count = |prefix: &str| log.iter().filter(|l| l.starts_with(prefix)).count();
+    let downs = count("dry run: Down(");
+    assert!(downs > 0);
+    assert_eq!(downs, count("dry run: Up(") + count("dry run: releasing held"));
+  }
+
+  #[test]
+  fn letter_hotkey_is_not_recorded() {
+    use schema::KeyAction::{Down, Up};
+    let state = test_state();
+    *state.hotkey_slot(Hotkey::Record).lock().unwrap() = Key::KeyR;
+    let send = |event_type| {
+      let event = Event { time: SystemTime::now(), name: None, event_type };
+      handle_event(&state, event);
+    };
+    send(EventType::KeyPress(Key::KeyR));
+    assert!(state.recording.load(Ordering::SeqCst));
+    send(EventType::KeyRelease(Key::KeyR));
+    send(EventType::KeyPress(Key::KeyB));
+    send(EventType::KeyRelease(Key::KeyB));
+    send(EventType::KeyPress(Key::KeyR));
+    send(EventType::KeyRelease(Key::KeyR));
+    assert!(!state.recording.load(Ordering::SeqCst));
+    let samples = state.samples.lock().unwrap();
+    let b = enigo::Key::Layout('b');
+    let actions: Vec<_> = samples[0].events.iter().map(|e| e.action).collect();
+    assert_eq!(actions, vec![Down(b), Up(b)]);
+  }
+
+  #[test]
+  fn escape_is_recorded_once_the_panic_key_moves() {
+    use schema::KeyAction::{Down, Up};
+    let state = test_state();
+    let send = |event_type| {
+      let event = Event { time: SystemTime::now(), name: None, event_type };
+      handle_event(&state, event);
+    };
+    // At its default binding Esc is the panic key and ends the take.
+    send(EventType::KeyPress(Key::F9));
+    send(EventType::KeyPress(Key::Escape));
+    assert!(!state.recording.load(Ordering::SeqCst));
+    send(EventType::KeyRelease(Key::Escape));
+    state.samples.lock().unwrap().clear();
+    *state.hotkey_slot(Hotkey::Panic).lock().unwrap() = Key::Pause;
+    send(EventType::KeyPress(Key::F9));
+    send(EventType::KeyPress(Key::Escape));
+    send(EventType::KeyRelease(Key::Escape));
+    assert!(state.recording.load(Ordering::SeqCst));
+    send(EventType::KeyPress(Key::F9));
+    let samples = state.samples.lock().unwrap();
+    let esc = enigo::Key::Escape;
+    let actions: Vec<_> = samples[0].events.iter().map(|e| e.action).collect();
+    assert_eq!(actions, vec![Down(esc), Up(esc)]);
+  }
+}
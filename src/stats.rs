@@ -0,0 +1,368 @@
+use crate::persist::key_to_string;
+use crate::schema::{KeyAction, TimedEvent};
+use crate::state::{Sample, key_label};
+use crate::transform::{self, TieOrder};
+use enigo::Key;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Mode {
+  Press,
+  Release,
+}
+
+/// How extreme values in a slot are discarded before summarizing.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum OutlierMethod {
+  None,
+  /// Drop values more than `k` standard deviations from the mean.
+  StdDev {
+    k: f64,
+  },
+  /// Drop values outside `q1 - factor * iqr ..= q3 + factor * iqr`.
+  Iqr {
+    factor: f64,
+  },
+}
+
+impl Default for OutlierMethod {
+  fn default() -> Self {
+    OutlierMethod::StdDev { k: 2.0 }
+  }
+}
+
+/// Timing distribution (in ms) of the `index`-th `mode` event of `key` across samples.
+#[derive(Clone, Debug)]
+pub struct SlotStats {
+  pub key: Key,
+  pub mode: Mode,
+  pub index: usize,
+  pub mean: f64,
+  pub median: f64,
+  pub std: f64,
+  pub min: f64,
+  pub max: f64,
+  pub n: usize,
+  /// The slot's values after outlier rejection, ascending.
+  pub values: Vec<f64>,
+}
+
+/// A slot as written by `stats_to_json`.
+#[derive(Serialize)]
+struct SlotStatsJson {
+  key: String,
+  mode: &'static str,
+  index: usize,
+  mean: f64,
+  median: f64,
+  std: f64,
+  min: f64,
+  max: f64,
+  n: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  values: Option<Vec<f64>>,
+}
+
+/// Pretty-printed JSON array of `stats` for analysis elsewhere. Keys use the save-file
+/// names; `raw` adds each slot's individual values, which can make the file large.
+pub fn stats_to_json(stats: &[SlotStats], raw: bool) -> serde_json::Result<String> {
+  let rows: Vec<SlotStatsJson> = stats
+    .iter()
+    .map(|slot| SlotStatsJson {
+      key: key_to_string(slot.key).unwrap_or_else(|| key_label(&slot.key)),
+      mode: match slot.mode {
+        Mode::Press => "press",
+        Mode::Release => "release",
+      },
+      index: slot.index,
+      mean: slot.mean,
+      median: slot.median,
+      std: slot.std,
+      min: slot.min,
+      max: slot.max,
+      n: slot.n,
+      values: raw.then(|| slot.values.clone()),
+    })
+    .collect();
+  serde_json::to_string_pretty(&rows)
+}
+
+/// Bucket key events per (key, mode, occurrence index) across all samples, aligning the
+/// n-th press of a key in one sample with the n-th press in every other sample.
+pub fn slot_values(samples: &[Sample]) -> HashMap<(Key, Mode, usize), Vec<f64>> {
+  let mut slots: HashMap<(Key, Mode, usize), Vec<f64>> = HashMap::new();
+  for sample in samples {
+    let mut seen: HashMap<(Key, Mode), usize> = HashMap::new();
+    for ev in &sample.events {
+      let (key, mode) = match ev.action {
+        KeyAction::Down(k) => (k, Mode::Press),
+        KeyAction::Up(k) => (k, Mode::Release),
+        _ => continue,
+      };
+      let index = seen.entry((key, mode)).or_insert(0);
+      slots
+        .entry((key, mode, *index))
+        .or_default()
+        .push(ev.at.as_secs_f64() * 1000.0);
+      *index += 1;
+    }
+  }
+  slots
+}
+
+/// Per-slot timing summary across `samples`. Every sample contributes at most one value
+/// to a slot, so each weighs the same in its mean however long the recording is.
+pub fn timing_stats(samples: &[Sample], outliers: OutlierMethod) -> Vec<SlotStats> {
+  let mut stats: Vec<SlotStats> = slot_values(samples)
+    .into_iter()
+    .map(|((key, mode, index), values)| {
+      summarize(key, mode, index, reject_outliers(values, outliers))
+    })
+    .collect();
+  stats.sort_by(|a, b| {
+    (key_label(&a.key), a.mode, a.index).cmp(&(key_label(&b.key), b.mode, b.index))
+  });
+  stats
+}
+
+/// One event per slot at the slot's mean time. Slots are keyed by the real `Key`, so
+/// named keys such as Space or Shift come back as themselves.
+pub fn averaged_timeline(samples: &[Sample], outliers: OutlierMethod) -> Vec<TimedEvent> {
+  let mut events: Vec<TimedEvent> = timing_stats(samples, outliers)
+    .into_iter()
+    .map(|slot| TimedEvent {
+      at: Duration::from_secs_f64(slot.mean.max(0.0) / 1000.0),
+      action: match slot.mode {
+        Mode::Press => KeyAction::Down(slot.key),
+        Mode::Release => KeyAction::Up(slot.key),
+      },
+    })
+    .collect();
+  transform::sort_events(&mut events, TieOrder::default());
+  events
+}
+
+/// Filter `values` by `method`. Never returns an empty set: if every value would be
+/// rejected the input is kept as is.
+pub fn reject_outliers(mut values: Vec<f64>, method: OutlierMethod) -> Vec<f64> {
+  if values.len() < 3 {
+    return values;
+  }
+  let (lo, hi) = match method {
+    OutlierMethod::None => return values,
+    OutlierMethod::StdDev { k } => {
+      let n = values.len() as f64;
+      let mean = values.iter().sum::<f64>() / n;
+      let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+      (mean - k * std, mean + k * std)
+    }
+    OutlierMethod::Iqr { factor } => {
+      values.sort_by(|a, b| a.total_cmp(b));
+      let q1 = quantile(&values, 0.25);
+      let q3 = quantile(&values, 0.75);
+      let iqr = q3 - q1;
+      (q1 - factor * iqr, q3 + factor * iqr)
+    }
+  };
+  let kept: Vec<f64> = values
+    .iter()
+    .copied()
+    .filter(|v| (lo..=hi).contains(v))
+    .collect();
+  if kept.is_empty() { values } else { kept }
+}
+
+/// Linearly interpolated quantile of already sorted values.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+  let pos = q * (sorted.len() - 1) as f64;
+  let lower = pos.floor() as usize;
+  let upper = pos.ceil() as usize;
+  sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+fn summarize(key: Key, mode: Mode, index: usize, mut values: Vec<f64>) -> SlotStats {
+  values.sort_by(|a, b| a.total_cmp(b));
+  let n = values.len();
+  let mean = values.iter().sum::<f64>() / n as f64;
+  let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n as f64;
+  let median = if n % 2 == 1 {
+    values[n / 2]
+  } else {
+    (values[n / 2 - 1] + values[n / 2]) / 2.0
+  };
+  SlotStats {
+    key,
+    mode,
+    index,
+    mean,
+    median,
+    std: variance.sqrt(),
+    min: values[0],
+    max: values[n - 1],
+    n,
+    values,
+  }
+}
+
+/// Best constant shift of one timeline onto another.
+#[derive(Clone, Copy, Debug)]
+pub struct Alignment {
+  /// Add this to the take's times to line it up with the reference.
+  pub offset_ms: i64,
+  /// Root-mean-square error of the matched events after shifting.
+  pub residual_ms: f64,
+  pub matched: usize,
+}
+
+/// One event of a `diff`: present in both timelines, or only in one of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffEntry {
+  pub key: Key,
+  pub mode: Mode,
+  /// Occurrence of this key and mode, counting from 0.
+  pub index: usize,
+  /// Time in the reference; `None` for an extra event in the take.
+  pub reference: Option<Duration>,
+  /// Time in the take; `None` for an event the take is missing.
+  pub take: Option<Duration>,
+}
+
+impl DiffEntry {
+  /// How much later the take fired than the reference, in ms; `None` unless matched.
+  pub fn delta_ms(&self) -> Option<f64> {
+    let (reference, take) = (self.reference?, self.take?);
+    Some((take.as_secs_f64() - reference.as_secs_f64()) * 1000.0)
+  }
+}
+
+/// Match the n-th press (or release) of each key in `take` with the n-th one in
+/// `reference`. Unmatched events are reported as missing or extra. Entries are in
+/// timeline order.
+pub fn diff(reference: &[TimedEvent], take: &[TimedEvent]) -> Vec<DiffEntry> {
+  let slots = |events: &[TimedEvent]| {
+    let mut seen: HashMap<(Key, Mode), usize> = HashMap::new();
+    let mut slots = Vec::new();
+    for ev in events {
+      let (key, mode) = match ev.action {
+        KeyAction::Down(k) => (k, Mode::Press),
+        KeyAction::Up(k) => (k, Mode::Release),
+        _ => continue,
+      };
+      let index = seen.entry((key, mode)).or_insert(0);
+      slots.push(((key, mode, *index), ev.at));
+      *index += 1;
+    }
+    slots
+  };
+  let mut take: HashMap<(Key, Mode, usize), Duration> = slots(take).into_iter().collect();
+  let mut entries: Vec<DiffEntry> = slots(reference)
+    .into_iter()
+    .map(|((key, mode, index), at)| DiffEntry {
+      key,
+      mode,
+      index,
+      reference: Some(at),
+      take: take.remove(&(key, mode, index)),
+    })
+    .collect();
+  entries.extend(take.into_iter().map(|((key, mode, index), at)| DiffEntry {
+    key,
+    mode,
+    index,
+    reference: None,
+    take: Some(at),
+  }));
+  entries.sort_by_key(|e| (e.reference.or(e.take), e.take));
+  entries
+}
+
+/// Find the shift of `take` minimizing its squared error against `reference` over the
+/// events `diff` matches, which is the mean difference. `None` if the timelines share
+/// no key events.
+pub fn alignment(reference: &[TimedEvent], take: &[TimedEvent]) -> Option<Alignment> {
+  let diffs: Vec<f64> = diff(reference, take)
+    .iter()
+    .filter_map(|e| e.delta_ms())
+    .map(|d| -d)
+    .collect();
+  if diffs.is_empty() {
+    return None;
+  }
+  let mean = diffs.iter().sum::<f64>() / diffs.len() as f64;
+  let offset_ms = mean.round() as i64;
+  let residual = diffs
+    .iter()
+    .map(|d| (d - offset_ms as f64).powi(2))
+    .sum::<f64>()
+    / diffs.len() as f64;
+  Some(Alignment {
+    offset_ms,
+    residual_ms: residual.sqrt(),
+    matched: diffs.len(),
+  })
+}
+
+/// Offset in ms that best lines `take` up with `reference`; 0 if nothing matches.
+pub fn align(reference: &[TimedEvent], take: &[TimedEvent]) -> i64 {
+  alignment(reference, take).map_or(0, |a| a.offset_ms)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ev(ms: u64, action: KeyAction) -> TimedEvent {
+    TimedEvent { at: Duration::from_millis(ms), action }
+  }
+
+  #[test]
+  fn long_and_short_samples_weigh_equally_in_a_slot() {
+    let a = Key::Layout('a');
+    let mut long = vec![ev(100, KeyAction::Down(a)), ev(150, KeyAction::Up(a))];
+    for i in 0..50 {
+      let b = Key::Layout('b');
+      long.push(ev(200 + i * 20, KeyAction::Down(b)));
+      long.push(ev(210 + i * 20, KeyAction::Up(b)));
+    }
+    let short = vec![ev(200, KeyAction::Down(a)), ev(250, KeyAction::Up(a))];
+    let samples = [
+      Sample::new("long".into(), long),
+      Sample::new("short".into(), short),
+    ];
+    let stats = timing_stats(&samples, OutlierMethod::None);
+    let press = stats
+      .iter()
+      .find(|s| s.key == a && s.mode == Mode::Press && s.index == 0)
+      .unwrap();
+    assert_eq!(press.n, 2);
+    assert_eq!(press.mean, 150.0);
+  }
+
+  #[test]
+  fn averaged_timeline_keeps_named_keys() {
+    use KeyAction::{Down, Up};
+    let take = |offset: u64| {
+      vec![
+        ev(offset, Down(Key::Shift)),
+        ev(offset + 10, Down(Key::Space)),
+        ev(offset + 60, Up(Key::Space)),
+        ev(offset + 80, Up(Key::Shift)),
+      ]
+    };
+    let samples = [
+      Sample::new("one".into(), take(0)),
+      Sample::new("two".into(), take(20)),
+    ];
+    let actions: Vec<KeyAction> = averaged_timeline(&samples, OutlierMethod::None)
+      .iter()
+      .map(|e| e.action)
+      .collect();
+    assert_eq!(
+      actions,
+      vec![Down(Key::Shift), Down(Key::Space), Up(Key::Space), Up(Key::Shift)]
+    );
+  }
+}
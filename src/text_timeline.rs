@@ -0,0 +1,70 @@
+//! Hand-written timelines, one event per line: `a@0` presses `a` at 0 ms and `a^@80`
+//! releases it at 80 ms. Named keys such as `space` or `shift` are case-insensitive.
+
+use crate::persist::string_to_key_loose;
+use crate::schema::{KeyAction, TimedEvent};
+use crate::transform::{self, TieOrder};
+use enigo::Key;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct ParseError {
+  /// 1-based line number of the offending line.
+  pub line: usize,
+  pub message: String,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.message)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse `key@ms` (press) and `key^@ms` (release) lines into a timeline sorted by time.
+/// Blank lines are ignored.
+pub fn parse_text_timeline(input: &str) -> Result<Vec<TimedEvent>, ParseError> {
+  let mut events = Vec::new();
+  for (i, raw) in input.lines().enumerate() {
+    let text = raw.trim();
+    if text.is_empty() {
+      continue;
+    }
+    let err = |message: String| ParseError { line: i + 1, message };
+    let (key, ms) = text
+      .rsplit_once('@')
+      .ok_or_else(|| err(format!("expected `key@ms`, found `{}`", text)))?;
+    let ms: u64 = ms
+      .trim()
+      .parse()
+      .map_err(|_| err(format!("bad time `{}`", ms)))?;
+    let (name, release) = match key.strip_suffix('^') {
+      Some(name) if !name.is_empty() => (name, true),
+      _ => (key, false),
+    };
+    let key = string_to_key_loose(name).ok_or_else(|| err(format!("unknown key `{}`", name)))?;
+    let action = if release { KeyAction::Up(key) } else { KeyAction::Down(key) };
+    events.push(TimedEvent {
+      at: Duration::from_millis(ms),
+      action,
+    });
+  }
+  transform::sort_events(&mut events, TieOrder::default());
+  Ok(events)
+}
+
+/// A press of `key` at `at_ms` held for `dur_ms`.
+pub fn hold(key: Key, at_ms: u64, dur_ms: u64) -> [TimedEvent; 2] {
+  [
+    TimedEvent {
+      at: Duration::from_millis(at_ms),
+      action: KeyAction::Down(key),
+    },
+    TimedEvent {
+      at: Duration::from_millis(at_ms + dur_ms),
+      action: KeyAction::Up(key),
+    },
+  ]
+}
@@ -0,0 +1,164 @@
+use auto_note::schema::{KeyAction, TimedEvent};
+use auto_note::state::key_label;
+use eframe::egui::{self, Color32, Rect, Stroke};
+use enigo::Key;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const LANE_HEIGHT: f32 = 18.0;
+/// Space reserved left of time zero for the lane labels.
+const LABEL_WIDTH: f32 = 70.0;
+const TICK_WIDTH: f32 = 3.0;
+const DOWN_COLOR: Color32 = Color32::from_rgb(120, 220, 120);
+const UP_COLOR: Color32 = Color32::from_rgb(220, 120, 120);
+const HOLD_COLOR: Color32 = Color32::from_rgb(70, 110, 70);
+const PLAYHEAD_COLOR: Color32 = Color32::YELLOW;
+const REGION_COLOR: Color32 = Color32::from_rgba_premultiplied(20, 35, 55, 40);
+const MARKER_COLOR: Color32 = Color32::from_rgb(80, 140, 220);
+/// Width of the grab area around a loop marker, in pixels.
+const MARKER_GRAB: f32 = 8.0;
+
+/// Distinct keys in order of first appearance, one lane each.
+fn lanes(events: &[TimedEvent]) -> Vec<Key> {
+  let mut keys: Vec<Key> = Vec::new();
+  for ev in events {
+    if let KeyAction::Down(k) | KeyAction::Up(k) = ev.action
+      && !keys.contains(&k)
+    {
+      keys.push(k);
+    }
+  }
+  keys
+}
+
+/// Draw the key events as a horizontally scrolling piano-roll.
+/// `zoom` is in pixels per second; `playhead` marks the playback position, if any.
+/// A loop `region` is shaded with draggable start and end markers; returns whether a
+/// drag moved it.
+pub fn show(
+  ui: &mut egui::Ui,
+  id_source: impl std::hash::Hash,
+  events: &[TimedEvent],
+  zoom: f32,
+  playhead: Option<Duration>,
+  region: &mut Option<(Duration, Duration)>,
+) -> bool {
+  let lanes = lanes(events);
+  if lanes.is_empty() {
+    ui.label("No key events.");
+    return false;
+  }
+  let mut changed = false;
+  let end = events.iter().map(|e| e.at).max().unwrap_or_default();
+  let size = egui::vec2(
+    LABEL_WIDTH + end.as_secs_f32() * zoom + 20.0,
+    lanes.len() as f32 * LANE_HEIGHT,
+  );
+
+  egui::ScrollArea::horizontal()
+    .id_source(id_source)
+    .show(ui, |ui| {
+      let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+      let painter = ui.painter_at(rect);
+      let x_at = |at: Duration| rect.left() + LABEL_WIDTH + at.as_secs_f32() * zoom;
+      let lane_rect = |lane: usize, x0: f32, x1: f32| {
+        let top = rect.top() + lane as f32 * LANE_HEIGHT;
+        Rect::from_min_max(
+          egui::pos2(x0, top + 2.0),
+          egui::pos2(x1, top + LANE_HEIGHT - 2.0),
+        )
+      };
+      let lane_of = |key: &Key| lanes.iter().position(|k| k == key).unwrap_or(0);
+
+      if let Some((start, end)) = region.as_mut() {
+        let shifted =
+          |at: Duration, dx: f32| Duration::from_secs_f32((at.as_secs_f32() + dx / zoom).max(0.0));
+        let dx = marker_drag(ui, ui.id().with("loop-start"), x_at(*start), rect);
+        if dx != 0.0 {
+          *start = shifted(*start, dx).min(*end);
+          changed = true;
+        }
+        let dx = marker_drag(ui, ui.id().with("loop-end"), x_at(*end), rect);
+        if dx != 0.0 {
+          *end = shifted(*end, dx).max(*start);
+          changed = true;
+        }
+        let shade = Rect::from_min_max(
+          egui::pos2(x_at(*start), rect.top()),
+          egui::pos2(x_at(*end), rect.bottom()),
+        );
+        painter.rect_filled(shade, 0.0, REGION_COLOR);
+        for at in [*start, *end] {
+          painter.vline(x_at(at), rect.y_range(), Stroke::new(2.0, MARKER_COLOR));
+        }
+      }
+
+      let font = egui::TextStyle::Small.resolve(ui.style());
+      for (lane, key) in lanes.iter().enumerate() {
+        let mid = rect.top() + (lane as f32 + 0.5) * LANE_HEIGHT;
+        painter.text(
+          egui::pos2(rect.left() + 4.0, mid),
+          egui::Align2::LEFT_CENTER,
+          key_label(key),
+          font.clone(),
+          ui.visuals().text_color(),
+        );
+      }
+
+      // Hold bars first so the Down/Up ticks stay visible on top.
+      let mut held: HashMap<Key, Duration> = HashMap::new();
+      for ev in events {
+        match ev.action {
+          KeyAction::Down(k) => {
+            held.entry(k).or_insert(ev.at);
+          }
+          KeyAction::Up(k) => {
+            if let Some(start) = held.remove(&k) {
+              painter.rect_filled(
+                lane_rect(lane_of(&k), x_at(start), x_at(ev.at)),
+                2.0,
+                HOLD_COLOR,
+              );
+            }
+          }
+          _ => {}
+        }
+      }
+      for ev in events {
+        let (key, color) = match ev.action {
+          KeyAction::Down(k) => (k, DOWN_COLOR),
+          KeyAction::Up(k) => (k, UP_COLOR),
+          _ => continue,
+        };
+        let x = x_at(ev.at);
+        painter.rect_filled(
+          lane_rect(lane_of(&key), x - TICK_WIDTH / 2.0, x + TICK_WIDTH / 2.0),
+          0.0,
+          color,
+        );
+      }
+
+      if let Some(at) = playhead {
+        let x = x_at(at);
+        painter.vline(x, rect.y_range(), Stroke::new(1.5, PLAYHEAD_COLOR));
+        let follow = Rect::from_min_max(
+          egui::pos2(x, rect.top()),
+          egui::pos2(x + 1.0, rect.bottom()),
+        );
+        ui.scroll_to_rect(follow, Some(egui::Align::Center));
+      }
+    });
+  changed
+}
+
+/// Horizontal drag distance this frame on the marker drawn at `x`.
+fn marker_drag(ui: &egui::Ui, id: egui::Id, x: f32, rect: Rect) -> f32 {
+  let grab = Rect::from_min_max(
+    egui::pos2(x - MARKER_GRAB / 2.0, rect.top()),
+    egui::pos2(x + MARKER_GRAB / 2.0, rect.bottom()),
+  );
+  ui.interact(grab, id, egui::Sense::drag())
+    .on_hover_cursor(egui::CursorIcon::ResizeHorizontal)
+    .drag_delta()
+    .x
+}
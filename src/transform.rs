@@ -0,0 +1,699 @@
+use crate::schema::{KeyAction, TimedEvent};
+use enigo::Key;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// How presses and releases of different inputs that share a timestamp are ordered
+/// by `sort_events`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieOrder {
+  /// Releases first, so one key is let go before the next goes down.
+  #[default]
+  UpsFirst,
+  /// Presses first, so the keys briefly overlap.
+  DownsFirst,
+}
+
+/// Sort `events` by time. Events sharing a timestamp are put in a fixed order: pointer
+/// moves and typed text first, then the presses and releases by `order`. An input both
+/// pressed and released at that time keeps its Down before its Up, unless it was
+/// already held going in; then it is released and pressed again, Up before Down.
+pub fn sort_events(events: &mut [TimedEvent], order: TieOrder) {
+  events.sort_by_key(|e| e.at);
+  let mut held: Vec<KeyAction> = Vec::new();
+  let mut start = 0;
+  while start < events.len() {
+    let at = events[start].at;
+    let end = start + events[start..].iter().take_while(|e| e.at == at).count();
+    if end - start > 1 {
+      order_ties(&mut events[start..end], order, &held);
+    }
+    for ev in &events[start..end] {
+      update_held(&mut held, ev.action);
+    }
+    start = end;
+  }
+}
+
+/// Reorder events that all share one timestamp; see `sort_events`. `held` lists the
+/// inputs already down before the group.
+fn order_ties(group: &mut [TimedEvent], order: TieOrder, held: &[KeyAction]) {
+  let (first_rank, last_rank) = match order {
+    TieOrder::UpsFirst => (1, 3),
+    TieOrder::DownsFirst => (3, 1),
+  };
+  let mut keyed: Vec<((u8, usize, u8), TimedEvent)> = group
+    .iter()
+    .enumerate()
+    .map(|(i, ev)| {
+      let action = &ev.action;
+      if !is_input(action) {
+        return ((0, i, 0), *ev);
+      }
+      let same: Vec<usize> = (0..group.len())
+        .filter(|&j| same_input(&group[j].action, action))
+        .collect();
+      let both = same.iter().any(|&j| is_press(&group[j].action))
+        && same.iter().any(|&j| !is_press(&group[j].action));
+      let key = if both {
+        // These sit between the plain releases and presses: Down then Up for a tap,
+        // Up then Down for a re-press of a held input.
+        let was_held = held.iter().any(|h| same_input(h, action));
+        (2, same[0], u8::from(is_press(action) == was_held))
+      } else if is_press(action) {
+        (last_rank, i, 0)
+      } else {
+        (first_rank, i, 0)
+      };
+      (key, *ev)
+    })
+    .collect();
+  keyed.sort_by_key(|(key, _)| *key);
+  for (slot, (_, ev)) in group.iter_mut().zip(keyed) {
+    *slot = ev;
+  }
+}
+
+/// Snap each event to the nearest `60000 / bpm / division` ms grid line.
+/// Rounding is monotonic, so events sharing a slot keep their relative order.
+pub fn quantize(events: &mut [TimedEvent], bpm: f64, division: u32) {
+  if bpm <= 0.0 || division == 0 {
+    return;
+  }
+  let grid_ms = 60_000.0 / bpm / division as f64;
+  for ev in events.iter_mut() {
+    let ms = ev.at.as_secs_f64() * 1000.0;
+    let snapped = (ms / grid_ms).round() * grid_ms;
+    ev.at = Duration::from_secs_f64(snapped / 1000.0);
+  }
+}
+
+/// Stretch (`factor` > 1) or compress time around `anchor_ms`: each event moves to
+/// `anchor + (at - anchor) * factor`, saturating at 0. Order is preserved.
+pub fn time_scale(events: &mut [TimedEvent], factor: f64, anchor_ms: u64) {
+  if factor <= 0.0 {
+    return;
+  }
+  let anchor = anchor_ms as f64 / 1000.0;
+  for ev in events.iter_mut() {
+    let scaled = anchor + (ev.at.as_secs_f64() - anchor) * factor;
+    ev.at = Duration::from_secs_f64(scaled.max(0.0));
+  }
+}
+
+/// The part of `events` from `start` on, re-based so `start` becomes t=0. Keys and
+/// buttons held at `start` are pressed at t=0 and the pointer is moved to where it
+/// was, so the rest plays from the same state.
+pub fn start_from(events: &[TimedEvent], start: Duration) -> Vec<TimedEvent> {
+  let before = &events[..events.iter().take_while(|e| e.at < start).count()];
+  let pointer = before.iter().rev().find_map(|e| match e.action {
+    KeyAction::MouseMove { .. } => Some(e.action),
+    _ => None,
+  });
+  let restore = pointer
+    .into_iter()
+    .chain(held_inputs(before))
+    .map(|action| TimedEvent {
+      at: Duration::ZERO,
+      action,
+    });
+  let rest = events.iter().filter(|e| e.at >= start).map(|e| TimedEvent {
+    at: e.at - start,
+    action: e.action,
+  });
+  restore.chain(rest).collect()
+}
+
+/// The part of `events` between `start` and `end`, re-based like `start_from`. Keys and
+/// buttons still held at `end` are released there, so no pass leaves anything stuck.
+pub fn region(events: &[TimedEvent], start: Duration, end: Duration) -> Vec<TimedEvent> {
+  let len = end.saturating_sub(start);
+  let mut out: Vec<TimedEvent> = start_from(events, start)
+    .into_iter()
+    .filter(|e| e.at <= len)
+    .collect();
+  for action in held_inputs(&out) {
+    let action = match action {
+      KeyAction::Down(k) => KeyAction::Up(k),
+      KeyAction::ButtonDown(b) => KeyAction::ButtonUp(b),
+      KeyAction::GamepadDown(b) => KeyAction::GamepadUp(b),
+      other => other,
+    };
+    out.push(TimedEvent { at: len, action });
+  }
+  out
+}
+
+/// Presses in `events` without a later matching release, in press order.
+fn held_inputs(events: &[TimedEvent]) -> Vec<KeyAction> {
+  let mut held: Vec<KeyAction> = Vec::new();
+  for ev in events {
+    update_held(&mut held, ev.action);
+  }
+  held
+}
+
+/// Track one event in `held`: a press adds its input once, a release removes it.
+fn update_held(held: &mut Vec<KeyAction>, action: KeyAction) {
+  if !is_input(&action) {
+    return;
+  }
+  if is_press(&action) {
+    if !held.iter().any(|h| same_input(h, &action)) {
+      held.push(action);
+    }
+  } else {
+    held.retain(|h| !same_input(h, &action));
+  }
+}
+
+/// Whether `action` presses or releases a key, button or controller button.
+fn is_input(action: &KeyAction) -> bool {
+  matches!(
+    action,
+    KeyAction::Down(_)
+      | KeyAction::Up(_)
+      | KeyAction::ButtonDown(_)
+      | KeyAction::ButtonUp(_)
+      | KeyAction::GamepadDown(_)
+      | KeyAction::GamepadUp(_)
+  )
+}
+
+/// Whether `action` presses a key, button or controller button.
+fn is_press(action: &KeyAction) -> bool {
+  matches!(
+    action,
+    KeyAction::Down(_) | KeyAction::ButtonDown(_) | KeyAction::GamepadDown(_)
+  )
+}
+
+/// Whether two press/release actions refer to the same key, button or controller button.
+fn same_input(a: &KeyAction, b: &KeyAction) -> bool {
+  match (a, b) {
+    (KeyAction::Down(x) | KeyAction::Up(x), KeyAction::Down(y) | KeyAction::Up(y)) => x == y,
+    (
+      KeyAction::ButtonDown(x) | KeyAction::ButtonUp(x),
+      KeyAction::ButtonDown(y) | KeyAction::ButtonUp(y),
+    ) => x == y,
+    (
+      KeyAction::GamepadDown(x) | KeyAction::GamepadUp(x),
+      KeyAction::GamepadDown(y) | KeyAction::GamepadUp(y),
+    ) => x == y,
+    _ => false,
+  }
+}
+
+/// Play `events` backwards: each event lands at `total - at` and presses become
+/// releases (and vice versa) so holds keep their shape.
+pub fn reverse_timeline(events: &[TimedEvent]) -> Vec<TimedEvent> {
+  let total = events.iter().map(|e| e.at).max().unwrap_or_default();
+  let mut reversed: Vec<TimedEvent> = events
+    .iter()
+    .rev()
+    .map(|ev| TimedEvent {
+      at: total - ev.at,
+      action: match ev.action {
+        KeyAction::Down(k) => KeyAction::Up(k),
+        KeyAction::Up(k) => KeyAction::Down(k),
+        KeyAction::ButtonDown(b) => KeyAction::ButtonUp(b),
+        KeyAction::ButtonUp(b) => KeyAction::ButtonDown(b),
+        KeyAction::GamepadDown(b) => KeyAction::GamepadUp(b),
+        KeyAction::GamepadUp(b) => KeyAction::GamepadDown(b),
+        other => other,
+      },
+    })
+    .collect();
+  sort_events(&mut reversed, TieOrder::default());
+  reversed
+}
+
+/// Make every matched key hold last `target_ms` by moving its Up; the Down stays put.
+/// A hold is cut short where the next press of the same key begins.
+pub fn normalize_holds(events: &mut [TimedEvent], target_ms: u64) {
+  let target = Duration::from_millis(target_ms);
+  let mut held: HashMap<Key, Duration> = HashMap::new();
+  let mut moved: Vec<(usize, Duration)> = Vec::new();
+  for (i, ev) in events.iter().enumerate() {
+    match ev.action {
+      KeyAction::Down(k) => {
+        held.entry(k).or_insert(ev.at);
+      }
+      KeyAction::Up(k) => {
+        if let Some(down_at) = held.remove(&k) {
+          let next_down = events[i + 1..]
+            .iter()
+            .find(|e| matches!(e.action, KeyAction::Down(n) if n == k))
+            .map(|e| e.at);
+          let up_at = down_at + target;
+          moved.push((i, next_down.map_or(up_at, |next| up_at.min(next))));
+        }
+      }
+      _ => {}
+    }
+  }
+  for (i, at) in moved {
+    events[i].at = at;
+  }
+  sort_events(events, TieOrder::default());
+}
+
+/// A run of events that must move together, spanning `start..=end`.
+struct Block {
+  members: Vec<usize>,
+  start: Duration,
+  end: Duration,
+  /// Only key events; anything else pins the block in place.
+  movable: bool,
+}
+
+/// Reorder independent key presses. Presses whose holds overlap or touch stay
+/// together as one block, mouse and button events stay where they are, and the
+/// shuffled blocks between them are laid out again with the original gaps, so
+/// every Down keeps its Up and the total length is unchanged.
+pub fn shuffle_independent(events: &[TimedEvent], seed: u64) -> Vec<TimedEvent> {
+  let end = events.iter().map(|e| e.at).max().unwrap_or_default();
+  // Each event's span; a key's Down and Up both cover the hold between them.
+  let mut spans: Vec<(Duration, Duration)> = events.iter().map(|e| (e.at, e.at)).collect();
+  let mut held: HashMap<Key, usize> = HashMap::new();
+  for (i, ev) in events.iter().enumerate() {
+    match ev.action {
+      KeyAction::Down(k) => {
+        held.entry(k).or_insert(i);
+      }
+      KeyAction::Up(k) => {
+        if let Some(down) = held.remove(&k) {
+          spans[down].1 = ev.at;
+          spans[i].0 = events[down].at;
+        }
+      }
+      _ => {}
+    }
+  }
+  for down in held.into_values() {
+    spans[down].1 = end;
+  }
+
+  let mut order: Vec<usize> = (0..events.len()).collect();
+  order.sort_by_key(|&i| spans[i].0);
+  let mut blocks: Vec<Block> = Vec::new();
+  for i in order {
+    let is_key = matches!(events[i].action, KeyAction::Down(_) | KeyAction::Up(_));
+    match blocks.last_mut() {
+      Some(block) if spans[i].0 <= block.end => {
+        block.members.push(i);
+        block.end = block.end.max(spans[i].1);
+        block.movable &= is_key;
+      }
+      _ => blocks.push(Block {
+        members: vec![i],
+        start: spans[i].0,
+        end: spans[i].1,
+        movable: is_key,
+      }),
+    }
+  }
+
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut shuffled = events.to_vec();
+  let mut i = 0;
+  while i < blocks.len() {
+    if !blocks[i].movable {
+      i += 1;
+      continue;
+    }
+    let run_end = blocks[i..]
+      .iter()
+      .position(|b| !b.movable)
+      .map_or(blocks.len(), |n| i + n);
+    let run = &blocks[i..run_end];
+    let gaps: Vec<Duration> = run.windows(2).map(|w| w[1].start - w[0].end).collect();
+    let mut order: Vec<&Block> = run.iter().collect();
+    order.shuffle(&mut rng);
+    let mut cursor = run[0].start;
+    for (n, block) in order.iter().enumerate() {
+      for &m in &block.members {
+        shuffled[m].at = cursor + (events[m].at - block.start);
+      }
+      cursor += block.end - block.start;
+      if let Some(gap) = gaps.get(n) {
+        cursor += *gap;
+      }
+    }
+    i = run_end;
+  }
+  sort_events(&mut shuffled, TieOrder::default());
+  shuffled
+}
+
+/// The character a US-layout key produces with Shift, e.g. `'1'` gives `'!'`.
+fn shifted_char(c: char) -> Option<char> {
+  if c.is_ascii_lowercase() {
+    return Some(c.to_ascii_uppercase());
+  }
+  let shifted = match c {
+    '1' => '!',
+    '2' => '@',
+    '3' => '#',
+    '4' => '$',
+    '5' => '%',
+    '6' => '^',
+    '7' => '&',
+    '8' => '*',
+    '9' => '(',
+    '0' => ')',
+    '-' => '_',
+    '=' => '+',
+    '[' => '{',
+    ']' => '}',
+    '\\' => '|',
+    ';' => ':',
+    '\'' => '"',
+    ',' => '<',
+    '.' => '>',
+    '/' => '?',
+    '`' => '~',
+    _ => return None,
+  };
+  Some(shifted)
+}
+
+/// Turn characters pressed while Shift is held (and no other modifier) into
+/// `KeyAction::Type` of the shifted glyph, dropping their Up events. A Shift hold
+/// that contained nothing but folded characters is dropped as well; otherwise it
+/// stays so shift-clicks and the like still see it.
+pub fn fold_shifted_typing(events: &[TimedEvent]) -> Vec<TimedEvent> {
+  let mut actions: Vec<Option<KeyAction>> = events.iter().map(|e| Some(e.action)).collect();
+  let mut held: HashSet<Key> = HashSet::new();
+  let mut folded: HashSet<Key> = HashSet::new();
+  let mut shift_down: Option<usize> = None;
+  let mut folded_in_hold = 0;
+  let mut only_folded = true;
+  for (i, ev) in events.iter().enumerate() {
+    let plain = held.iter().all(|k| matches!(k, Key::Layout(_)));
+    match ev.action {
+      KeyAction::Down(Key::Shift) => {
+        if shift_down.is_none() {
+          shift_down = Some(i);
+          folded_in_hold = 0;
+          only_folded = true;
+        }
+        continue;
+      }
+      KeyAction::Up(Key::Shift) => {
+        if let Some(down) = shift_down.take()
+          && only_folded
+          && folded_in_hold > 0
+        {
+          actions[down] = None;
+          actions[i] = None;
+        }
+        continue;
+      }
+      KeyAction::Down(key @ Key::Layout(c)) if shift_down.is_some() && plain => {
+        if let Some(glyph) = shifted_char(c) {
+          actions[i] = Some(KeyAction::Type(glyph));
+          folded.insert(key);
+          held.insert(key);
+          folded_in_hold += 1;
+          continue;
+        }
+      }
+      KeyAction::Up(key) if folded.remove(&key) => {
+        actions[i] = None;
+        held.remove(&key);
+        continue;
+      }
+      _ => {}
+    }
+    match ev.action {
+      KeyAction::Down(k) => {
+        held.insert(k);
+      }
+      KeyAction::Up(k) => {
+        held.remove(&k);
+      }
+      _ => {}
+    }
+    if !matches!(ev.action, KeyAction::MouseMove { .. }) {
+      only_folded = false;
+    }
+  }
+  events
+    .iter()
+    .zip(actions)
+    .filter_map(|(ev, action)| action.map(|action| TimedEvent { at: ev.at, action }))
+    .collect()
+}
+
+/// Longest ramp `smooth_movement` adds on each side of a direction change, at factor 1.
+const SMOOTH_MAX_MS: f64 = 120.0;
+/// Length of one tap slot in a smoothing ramp.
+const SMOOTH_PULSE_MS: f64 = 20.0;
+
+/// Soften direction changes between movement `keys` (e.g. WASD): a key pressed within
+/// the ramp window of another key's release fades in as taps of growing length before
+/// its press, and the released key fades out as shrinking taps after its release.
+/// `factor` (0 to 1) scales the window up to 120 ms; 0 returns the events unchanged.
+/// Ramps stay within the gaps between a key's own presses and releases.
+pub fn smooth_movement(events: &[TimedEvent], keys: &[Key], factor: f64) -> Vec<TimedEvent> {
+  let window = Duration::from_secs_f64(SMOOTH_MAX_MS * factor.clamp(0.0, 1.0) / 1000.0);
+  let pulse = Duration::from_secs_f64(SMOOTH_PULSE_MS / 1000.0);
+  let taps = (window.as_secs_f64() / pulse.as_secs_f64()).floor() as u32;
+  // (time, key, is press) for every movement key event.
+  let moves: Vec<(Duration, Key, bool)> = events
+    .iter()
+    .filter_map(|e| match e.action {
+      KeyAction::Down(k) if keys.contains(&k) => Some((e.at, k, true)),
+      KeyAction::Up(k) if keys.contains(&k) => Some((e.at, k, false)),
+      _ => None,
+    })
+    .collect();
+  let mut out = events.to_vec();
+  if taps == 0 {
+    return out;
+  }
+  let tap = |out: &mut Vec<TimedEvent>, key: Key, down_at: Duration, up_at: Duration| {
+    out.push(TimedEvent {
+      at: down_at,
+      action: KeyAction::Down(key),
+    });
+    out.push(TimedEvent {
+      at: up_at,
+      action: KeyAction::Up(key),
+    });
+  };
+  for (i, &(at, key, down)) in moves.iter().enumerate() {
+    let changes_direction = moves.iter().any(|&(other_at, other, other_down)| {
+      other != key && other_down != down && other_at.abs_diff(at) <= window
+    });
+    if !changes_direction {
+      continue;
+    }
+    if down {
+      // Fade in, but only in the later half of the gap since this key's last event.
+      let free_from = moves[..i]
+        .iter()
+        .rev()
+        .find(|m| m.1 == key)
+        .map_or(Duration::ZERO, |m| m.0 + (at - m.0) / 2);
+      let ramp_start = at.saturating_sub(window).max(free_from);
+      for n in 0..taps {
+        let slot = ramp_start + pulse * n;
+        if slot + pulse > at {
+          break;
+        }
+        let duty = f64::from(n + 1) / f64::from(taps + 1);
+        tap(&mut out, key, slot, slot + pulse.mul_f64(duty));
+      }
+    } else {
+      // Fade out within the earlier half of the gap before this key's next event.
+      let free_until = moves[i + 1..]
+        .iter()
+        .find(|m| m.1 == key)
+        .map_or(Duration::MAX, |m| at + (m.0 - at) / 2);
+      for n in 0..taps {
+        let slot = at + pulse * n;
+        if slot + pulse > free_until {
+          break;
+        }
+        let duty = f64::from(taps - n) / f64::from(taps + 1);
+        tap(
+          &mut out,
+          key,
+          slot + pulse.mul_f64(1.0 - duty),
+          slot + pulse,
+        );
+      }
+    }
+  }
+  sort_events(&mut out, TieOrder::default());
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ev(ms: u64, action: KeyAction) -> TimedEvent {
+    TimedEvent { at: Duration::from_millis(ms), action }
+  }
+
+  fn actions(events: &[TimedEvent]) -> Vec<KeyAction> {
+    events.iter().map(|e| e.action).collect()
+  }
+
+  /// Everything at 10 ms: a release of `a` (held since 0), a press of `b`, a tap of
+  /// `c` recorded Up first, and a pointer move.
+  fn colliding() -> Vec<TimedEvent> {
+    let (a, b, c) = (Key::Layout('a'), Key::Layout('b'), Key::Layout('c'));
+    vec![
+      ev(10, KeyAction::Up(c)),
+      ev(10, KeyAction::Down(b)),
+      ev(10, KeyAction::Up(a)),
+      ev(10, KeyAction::MouseMove { x: 1.0, y: 2.0 }),
+      ev(10, KeyAction::Down(c)),
+      ev(0, KeyAction::Down(a)),
+    ]
+  }
+
+  #[test]
+  fn sort_events_orders_ties_ups_first() {
+    let (a, b, c) = (Key::Layout('a'), Key::Layout('b'), Key::Layout('c'));
+    let mut events = colliding();
+    sort_events(&mut events, TieOrder::UpsFirst);
+    assert_eq!(
+      actions(&events),
+      vec![
+        KeyAction::Down(a),
+        KeyAction::MouseMove { x: 1.0, y: 2.0 },
+        KeyAction::Up(a),
+        KeyAction::Down(c),
+        KeyAction::Up(c),
+        KeyAction::Down(b),
+      ]
+    );
+  }
+
+  #[test]
+  fn sort_events_orders_ties_downs_first() {
+    let (a, b, c) = (Key::Layout('a'), Key::Layout('b'), Key::Layout('c'));
+    let mut events = colliding();
+    sort_events(&mut events, TieOrder::DownsFirst);
+    assert_eq!(
+      actions(&events),
+      vec![
+        KeyAction::Down(a),
+        KeyAction::MouseMove { x: 1.0, y: 2.0 },
+        KeyAction::Down(b),
+        KeyAction::Down(c),
+        KeyAction::Up(c),
+        KeyAction::Up(a),
+      ]
+    );
+  }
+
+  #[test]
+  fn sort_events_ignores_input_order_of_ties() {
+    let mut forward = colliding();
+    let mut backward = colliding();
+    backward.reverse();
+    sort_events(&mut forward, TieOrder::UpsFirst);
+    sort_events(&mut backward, TieOrder::UpsFirst);
+    assert_eq!(actions(&forward), actions(&backward));
+  }
+
+  #[test]
+  fn sort_events_releases_a_held_key_before_pressing_it_again() {
+    let (a, b) = (Key::Layout('a'), Key::Layout('b'));
+    // `a` is held from 0, let go and pressed again at 10, and released at 20; `b` is
+    // tapped at 10.
+    let events = vec![
+      ev(10, KeyAction::Down(a)),
+      ev(10, KeyAction::Up(b)),
+      ev(10, KeyAction::Up(a)),
+      ev(10, KeyAction::Down(b)),
+      ev(0, KeyAction::Down(a)),
+      ev(20, KeyAction::Up(a)),
+    ];
+    for order in [TieOrder::UpsFirst, TieOrder::DownsFirst] {
+      let mut sorted = events.clone();
+      sort_events(&mut sorted, order);
+      assert_eq!(
+        actions(&sorted),
+        vec![
+          KeyAction::Down(a),
+          KeyAction::Up(a),
+          KeyAction::Down(a),
+          KeyAction::Down(b),
+          KeyAction::Up(b),
+          KeyAction::Up(a),
+        ]
+      );
+    }
+  }
+
+  #[test]
+  fn normalize_holds_ends_a_hold_where_the_next_press_starts() {
+    let a = Key::Layout('a');
+    let mut events = vec![
+      ev(0, KeyAction::Down(a)),
+      ev(10, KeyAction::Up(a)),
+      ev(50, KeyAction::Down(a)),
+      ev(60, KeyAction::Up(a)),
+    ];
+    normalize_holds(&mut events, 100);
+    assert_eq!(
+      events,
+      vec![
+        ev(0, KeyAction::Down(a)),
+        ev(50, KeyAction::Up(a)),
+        ev(50, KeyAction::Down(a)),
+        ev(150, KeyAction::Up(a)),
+      ]
+    );
+  }
+
+  /// True when every key is pressed before it is released.
+  fn downs_precede_ups(events: &[TimedEvent]) -> bool {
+    let mut held = Vec::new();
+    events.iter().all(|e| match e.action {
+      KeyAction::Down(k) => {
+        held.push(k);
+        true
+      }
+      KeyAction::Up(k) => match held.iter().position(|&h| h == k) {
+        Some(i) => {
+          held.remove(i);
+          true
+        }
+        None => false,
+      },
+      _ => true,
+    })
+  }
+
+  #[test]
+  fn reversing_twice_restores_the_timeline() {
+    let (a, b, c) = (Key::Layout('a'), Key::Layout('b'), Key::Layout('c'));
+    let original = vec![
+      ev(0, KeyAction::Down(a)),
+      ev(20, KeyAction::Down(b)),
+      ev(50, KeyAction::Up(a)),
+      ev(80, KeyAction::Up(b)),
+      ev(100, KeyAction::Down(c)),
+      ev(130, KeyAction::Up(c)),
+    ];
+    let reversed = reverse_timeline(&original);
+    assert!(downs_precede_ups(&reversed));
+    assert_eq!(reversed[0], ev(0, KeyAction::Down(c)));
+    let restored = reverse_timeline(&reversed);
+    assert!(downs_precede_ups(&restored));
+    assert_eq!(restored, original);
+  }
+}
@@ -0,0 +1,101 @@
+use crate::schema::{KeyAction, TimedEvent};
+use crate::state::key_label;
+use enigo::Key;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationIssue {
+  /// A Down with no later Up; plays back as a stuck key.
+  UnmatchedDown { key: Key, index: usize },
+  /// An Up for a key that was not held.
+  UpWithoutDown { key: Key, index: usize },
+  /// A second Down for a key that is already held.
+  OverlappingHold { key: Key, index: usize },
+}
+
+impl fmt::Display for ValidationIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValidationIssue::UnmatchedDown { key, index } => {
+        write!(
+          f,
+          "#{}: {} pressed but never released",
+          index,
+          key_label(key)
+        )
+      }
+      ValidationIssue::UpWithoutDown { key, index } => {
+        write!(f, "#{}: {} released without a press", index, key_label(key))
+      }
+      ValidationIssue::OverlappingHold { key, index } => {
+        write!(f, "#{}: {} pressed again while held", index, key_label(key))
+      }
+    }
+  }
+}
+
+/// Check that every key Down is paired with a later Up and holds don't overlap.
+pub fn validate_timeline(events: &[TimedEvent]) -> Vec<ValidationIssue> {
+  let mut issues = Vec::new();
+  let mut held: HashMap<Key, usize> = HashMap::new();
+  for (index, ev) in events.iter().enumerate() {
+    match ev.action {
+      KeyAction::Down(key) => match held.entry(key) {
+        Entry::Occupied(_) => issues.push(ValidationIssue::OverlappingHold { key, index }),
+        Entry::Vacant(slot) => {
+          slot.insert(index);
+        }
+      },
+      KeyAction::Up(key) if held.remove(&key).is_none() => {
+        issues.push(ValidationIssue::UpWithoutDown { key, index });
+      }
+      _ => {}
+    }
+  }
+  let mut unmatched: Vec<(Key, usize)> = held.into_iter().collect();
+  unmatched.sort_by_key(|(_, index)| *index);
+  issues.extend(
+    unmatched
+      .into_iter()
+      .map(|(key, index)| ValidationIssue::UnmatchedDown { key, index }),
+  );
+  issues
+}
+
+/// Append an Up at the end of the timeline for every unmatched Down.
+/// Returns how many events were added.
+pub fn close_unmatched_downs(events: &mut Vec<TimedEvent>) -> usize {
+  let end = events.iter().map(|e| e.at).max().unwrap_or_default();
+  let keys: Vec<Key> = validate_timeline(events)
+    .into_iter()
+    .filter_map(|issue| match issue {
+      ValidationIssue::UnmatchedDown { key, .. } => Some(key),
+      _ => None,
+    })
+    .collect();
+  for key in &keys {
+    events.push(TimedEvent {
+      at: end,
+      action: KeyAction::Up(*key),
+    });
+  }
+  keys.len()
+}
+
+/// Remove every Down for a key that is already held, such as OS auto-repeat.
+/// Returns how many events were removed.
+pub fn remove_repeated_downs(events: &mut Vec<TimedEvent>) -> usize {
+  let before = events.len();
+  let mut held: HashSet<Key> = HashSet::new();
+  events.retain(|ev| match ev.action {
+    KeyAction::Down(key) => held.insert(key),
+    KeyAction::Up(key) => {
+      held.remove(&key);
+      true
+    }
+    _ => true,
+  });
+  before - events.len()
+}